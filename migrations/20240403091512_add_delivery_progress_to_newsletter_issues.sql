@@ -0,0 +1,22 @@
+ALTER TABLE newsletter_issues ADD COLUMN n_enqueued INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE newsletter_issues ADD COLUMN n_sent INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE newsletter_issues ADD COLUMN n_failed INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE newsletter_issues ADD COLUMN queued_at timestamptz NULL;
+ALTER TABLE newsletter_issues ADD COLUMN first_sent_at timestamptz NULL;
+ALTER TABLE newsletter_issues ADD COLUMN last_sent_at timestamptz NULL;
+-- Best effort for issues published before we kept track of progress: we
+-- know what is left to deliver and what failed, not what went out.
+UPDATE newsletter_issues i
+SET
+    n_failed = (
+        SELECT count(*) FROM failed_deliveries f
+        WHERE f.newsletter_issue_id = i.newsletter_issue_id
+    ),
+    n_enqueued = (
+        SELECT count(*) FROM failed_deliveries f
+        WHERE f.newsletter_issue_id = i.newsletter_issue_id
+    ) + (
+        SELECT count(*) FROM issue_delivery_queue q
+        WHERE q.newsletter_issue_id = i.newsletter_issue_id
+    ),
+    queued_at = published_at::timestamptz;
@@ -0,0 +1,5 @@
+ALTER TABLE newsletter_issues DROP CONSTRAINT newsletter_issues_status_check;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_status_check
+    CHECK (status IN ('scheduled', 'published', 'paused', 'cancelled'));
+-- Deliveries that were still pending when their issue got cancelled
+ALTER TABLE newsletter_issues ADD COLUMN n_cancelled INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+-- Every subscriber gets a token to unsubscribe with, straight from the emails they receive
+CREATE TABLE unsubscribe_tokens (
+    unsubscribe_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL UNIQUE REFERENCES subscriptions (id),
+    PRIMARY KEY (unsubscribe_token)
+);
+-- 25 hex characters taken from a random UUID, the same shape as the tokens we generate
+INSERT INTO unsubscribe_tokens (unsubscribe_token, subscriber_id)
+SELECT substr(replace(gen_random_uuid()::text, '-', ''), 1, 25), id
+FROM subscriptions;
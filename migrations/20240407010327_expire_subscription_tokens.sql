@@ -0,0 +1,6 @@
+-- Tokens issued before this migration get a full lifetime from now on
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE subscription_tokens ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '48 hours';
+ALTER TABLE subscription_tokens ALTER COLUMN created_at DROP DEFAULT;
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
+CREATE INDEX subscription_tokens_subscriber_id_idx ON subscription_tokens (subscriber_id);
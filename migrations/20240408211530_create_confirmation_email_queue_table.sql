@@ -0,0 +1,9 @@
+-- Confirmation emails left to the delivery worker, e.g. for imported
+-- subscribers. Revoking a token takes its pending email along with it.
+CREATE TABLE confirmation_email_queue (
+    subscription_token TEXT NOT NULL
+        REFERENCES subscription_tokens (subscription_token) ON DELETE CASCADE,
+    n_retries INTEGER NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscription_token)
+);
@@ -0,0 +1,9 @@
+-- One row per email that went out, for the delivery history of each subscriber
+CREATE TABLE sent_deliveries (
+    newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    sent_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
+CREATE INDEX sent_deliveries_subscriber_email_idx ON sent_deliveries (subscriber_email);
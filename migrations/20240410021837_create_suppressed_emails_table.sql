@@ -0,0 +1,7 @@
+-- Addresses erased at their owner's request. Only an HMAC is kept: enough to
+-- recognise them if they show up again in an import, not to read them back.
+CREATE TABLE suppressed_emails (
+    email_hash TEXT NOT NULL,
+    suppressed_at timestamptz NOT NULL,
+    PRIMARY KEY (email_hash)
+);
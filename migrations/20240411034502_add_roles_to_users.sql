@@ -0,0 +1,18 @@
+-- Whoever already has an account keeps every right they had
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
+ALTER TABLE users ADD COLUMN email TEXT UNIQUE;
+-- Disabled users cannot log in, and their sessions stop working
+ALTER TABLE users ADD COLUMN disabled_at timestamptz;
+
+-- Only a hash of each invitation token is kept, like password reset tokens
+CREATE TABLE user_invitations (
+    token_hash TEXT NOT NULL,
+    username TEXT NOT NULL UNIQUE,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
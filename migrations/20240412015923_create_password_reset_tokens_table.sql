@@ -0,0 +1,11 @@
+-- Only a hash of each token is kept: whoever reads the table cannot use them
+CREATE TABLE password_reset_tokens (
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
+CREATE INDEX password_reset_tokens_user_id_idx ON password_reset_tokens (user_id);
+-- Sessions remember the generation they were opened in; bumping it ends them all
+ALTER TABLE users ADD COLUMN session_generation INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Base32 TOTP secret of users who turned on two-factor authentication
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+-- The last time step a code was accepted for: a code cannot be used twice
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+-- Only a hash of each recovery code is kept, like password reset tokens
+CREATE TABLE recovery_codes (
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -1,12 +1,15 @@
 use std::ops::Deref;
 
+use crate::authentication::Role;
 use crate::session_state::TypedSession;
-use crate::utils::{e500, see_other};
+use crate::utils::{e403, e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{web, FromRequest, HttpMessage};
 use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use sqlx::PgPool;
 use uuid::Uuid;
 
 #[derive(Copy, Clone, Debug)]
@@ -25,6 +28,9 @@ impl Deref for UserId {
     }
 }
 
+/// Let logged-in users through, with their `UserId` and `Role` in the request
+/// extensions. The user is looked up on every request: disabling or deleting
+/// them, or invalidating their sessions, logs them out straight away.
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -33,16 +39,93 @@ pub async fn reject_anonymous_users(
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500("The database pool is missing from the application data"))?;
 
-    match session.get_user_id().map_err(e500)? {
+    let user_id = session.get_user_id().map_err(e500)?;
+    let role = match user_id {
         Some(user_id) => {
+            let session_generation = session.get_session_generation().map_err(e500)?;
+            get_active_user_role(&pool, user_id, session_generation)
+                .await
+                .map_err(e500)?
+        }
+        None => None,
+    };
+    match (user_id, role) {
+        (Some(user_id), Some(role)) => {
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
             next.call(req).await
         }
-        None => {
+        (Some(_), None) => {
+            session.log_out();
+            let response = see_other("/login");
+            let e = anyhow::anyhow!(
+                "The user has been disabled or deleted, or the session invalidated"
+            );
+            Err(InternalError::from_response(e, response).into())
+        }
+        (None, _) => {
             let response = see_other("/login");
             let e = anyhow::anyhow!("The user has not logged in");
             Err(InternalError::from_response(e, response).into())
         }
     }
 }
+
+/// Only let editors and owners through. Goes behind `reject_anonymous_users`.
+pub async fn require_editor(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Editor, req, next).await
+}
+
+/// Only let owners through. Goes behind `reject_anonymous_users`.
+pub async fn require_owner(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Owner, req, next).await
+}
+
+async fn require_role(
+    required: Role,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let role = req.extensions().get::<Role>().copied();
+    match role {
+        Some(role) if role.includes(required) => next.call(req).await,
+        _ => Err(e403(format!(
+            "Only users with the {} role or above can do this.",
+            required.as_str()
+        ))),
+    }
+}
+
+/// `None` if the user is gone, disabled, or logged out of `session_generation`.
+#[tracing::instrument(name = "Get the role of an active user", skip(pool))]
+async fn get_active_user_role(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_generation: i32,
+) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role
+        FROM users
+        WHERE user_id = $1 AND disabled_at IS NULL AND session_generation = $2
+        "#,
+        user_id,
+        session_generation,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the role of a user.")?;
+    row.map(|r| Role::parse(&r.role).map_err(anyhow::Error::msg))
+        .transpose()
+}
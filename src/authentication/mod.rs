@@ -1,6 +1,16 @@
 mod middleware;
 mod password;
-// pub use middleware::reject_anonymous_users;
-pub use middleware::reject_anonymous_users;
-pub use middleware::UserId;
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+mod role;
+mod sessions;
+mod two_factor;
+pub use middleware::{reject_anonymous_users, require_editor, require_owner, UserId};
+pub use password::{
+    change_password, check_password_strength, create_user, validate_credentials, AuthError,
+    Credentials, PasswordHashing,
+};
+pub use role::Role;
+pub use sessions::{get_session_generation, invalidate_sessions};
+pub use two_factor::{
+    disable_two_factor, generate_totp_secret, has_two_factor, matching_time_step,
+    replace_recovery_codes, totp, verify_second_factor,
+};
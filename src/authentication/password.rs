@@ -2,7 +2,12 @@ use anyhow::Context;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::authentication::Role;
+use crate::configuration::PasswordHashingSettings;
 
 use crate::telemetry::spawn_blocking_with_tracing;
 
@@ -19,18 +24,53 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// The argon2 parameters new password hashes are computed with.
+///
+/// Unknown usernames are checked against a dummy hash computed with the same
+/// parameters, so they take as long to reject as wrong passwords.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    dummy_hash: Secret<String>,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = settings
+            .params()
+            .context("Invalid argon2 parameters in the configuration.")?;
+        let dummy_password = Secret::new(Uuid::new_v4().to_string());
+        let dummy_hash = compute_password_hash(dummy_password, &params)?;
+        Ok(Self { params, dummy_hash })
+    }
+
+    /// Whether `password_hash` was computed with other parameters than the
+    /// current ones.
+    fn is_outdated(&self, password_hash: &Secret<String>) -> bool {
+        let Ok(password_hash) = PasswordHash::new(password_hash.expose_secret()) else {
+            return false;
+        };
+        let Ok(params) = Params::try_from(&password_hash) else {
+            return true;
+        };
+        password_hash.algorithm != Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+}
+
+/// Rehashes the password in the background if its stored hash uses outdated
+/// argon2 parameters.
+#[tracing::instrument(name = "Validate credentials", skip(credentials, hashing, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-            gZiV/M1gPc22ElAH/Jh1Hw$\
-            CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    let mut expected_password_hash = hashing.dummy_hash.clone();
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool)
             .await
@@ -39,8 +79,9 @@ pub async fn validate_credentials(
         user_id = Some(stored_user_id);
         expected_password_hash = stored_password_hash;
     }
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let (password, expected_password_hash) = spawn_blocking_with_tracing(move || {
+        verify_password_hash(&expected_password_hash, &credentials.password)?;
+        Ok::<_, AuthError>((credentials.password, expected_password_hash))
     })
     .await
     .context("Failed to spawn blocking task.")??;
@@ -49,9 +90,61 @@ pub async fn validate_credentials(
     // with the provided password,
     // we never authenticate a non-existing user.
     // You can easily add a unit test for that precise scenario.
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+    if hashing.is_outdated(&expected_password_hash) {
+        let hashing = hashing.clone();
+        let pool = pool.clone();
+        let upgrade = async move {
+            if let Err(e) =
+                upgrade_password_hash(user_id, password, expected_password_hash, &hashing, &pool)
+                    .await
+            {
+                tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to upgrade an outdated password hash",
+                );
+            }
+        };
+        tokio::spawn(upgrade.in_current_span());
+    }
+    Ok(user_id)
+}
+
+/// Replace a hash computed with outdated parameters, unless the password was
+/// changed in the meantime.
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(password, outdated_password_hash, hashing, pool)
+)]
+async fn upgrade_password_hash(
+    user_id: Uuid,
+    password: Secret<String>,
+    outdated_password_hash: Secret<String>,
+    hashing: &PasswordHashing,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let params = hashing.params.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &params))
+            .await?
+            .context("Failed to hash password")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        outdated_password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to upgrade user's password hash in the database.")?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -59,8 +152,8 @@ pub async fn validate_credentials(
     skip(expected_password_hash, password_candidate)
 )]
 fn verify_password_hash(
-    expected_password_hash: Secret<String>,
-    password_candidate: Secret<String>,
+    expected_password_hash: &Secret<String>,
+    password_candidate: &Secret<String>,
 ) -> Result<(), AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
@@ -82,7 +175,7 @@ async fn get_stored_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND disabled_at IS NULL
         "#,
         username,
     )
@@ -93,15 +186,18 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+#[tracing::instrument(name = "Change password", skip(password, hashing, pool))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let params = hashing.params.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &params))
+            .await?
+            .context("Failed to hash password")?;
     sqlx::query!(
         r#"
         UPDATE users
@@ -117,14 +213,62 @@ pub async fn change_password(
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
-    let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+/// Check that a new password is acceptable, returning what is wrong with it
+/// otherwise.
+pub fn check_password_strength(password: &Secret<String>) -> Result<(), String> {
+    let password_max_length = 129;
+    let password_min_length = 10;
+    let length = password.expose_secret().len();
+    if length > password_max_length || length < password_min_length {
+        return Err(format!(
+            "Passwords need to be {}-{} characters long.",
+            password_min_length, password_max_length
+        ));
+    }
+    Ok(())
+}
+
+/// Returns `None` if the username or the email address is already taken.
+#[tracing::instrument(name = "Create user", skip(transaction, password, hashing))]
+pub async fn create_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: &str,
+    role: Role,
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let params = hashing.params.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &params))
+            .await?
+            .context("Failed to hash password")?;
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, role, password_hash)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        email,
+        role.as_str(),
+        password_hash.expose_secret(),
     )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to insert a new user in the database.")?;
+    Ok(row.map(|r| r.user_id))
+}
+
+fn compute_password_hash(
+    password: Secret<String>,
+    params: &Params,
+) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
@@ -0,0 +1,70 @@
+/// What a user is allowed to do in the admin area. Each role can do
+/// everything the roles below it can:
+///
+/// - viewers browse subscribers, issues and deliveries;
+/// - editors also publish newsletters and manage subscribers;
+/// - owners also manage the other users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+impl Role {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "owner" => Ok(Self::Owner),
+            "editor" => Ok(Self::Editor),
+            "viewer" => Ok(Self::Viewer),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Editor => "editor",
+            Self::Viewer => "viewer",
+        }
+    }
+
+    /// Whether this role is allowed everything `other` is.
+    pub fn includes(self, other: Role) -> bool {
+        self.rank() >= other.rank()
+    }
+
+    fn rank(self) -> u8 {
+        match self {
+            Self::Viewer => 0,
+            Self::Editor => 1,
+            Self::Owner => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::Role;
+    use claims::{assert_err, assert_ok_eq};
+
+    const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    #[test]
+    fn roles_round_trip_through_their_database_representation() {
+        for role in ALL {
+            assert_ok_eq!(Role::parse(role.as_str()), role);
+        }
+        assert_err!(Role::parse("admin"));
+    }
+
+    #[test]
+    fn owners_can_do_everything_and_viewers_only_what_everyone_can() {
+        for role in ALL {
+            assert!(Role::Owner.includes(role));
+            assert!(role.includes(Role::Viewer));
+        }
+        assert!(!Role::Viewer.includes(Role::Editor));
+        assert!(!Role::Editor.includes(Role::Owner));
+    }
+}
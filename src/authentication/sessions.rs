@@ -0,0 +1,29 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The generation new sessions of `user_id` are opened in.
+#[tracing::instrument(name = "Get session generation", skip(pool))]
+pub async fn get_session_generation(user_id: Uuid, pool: &PgPool) -> Result<i32, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT session_generation FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the session generation of a user.")?;
+    Ok(row.session_generation)
+}
+
+/// Log `user_id` out everywhere: sessions opened before this call stop working.
+#[tracing::instrument(name = "Invalidate sessions", skip(pool))]
+pub async fn invalidate_sessions(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "UPDATE users SET session_generation = session_generation + 1 WHERE user_id = $1",
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to invalidate the sessions of a user.")?;
+    Ok(())
+}
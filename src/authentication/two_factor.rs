@@ -0,0 +1,212 @@
+use anyhow::Context;
+use rand::{thread_rng, RngCore};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::domain::RecoveryCode;
+
+/// The name authenticator apps show next to the username.
+const TOTP_ISSUER: &str = "zero2prod";
+const TOTP_STEP: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// A new base32 encoded RFC 6238 secret, 160 bits long as RFC 4226 recommends.
+pub fn generate_totp_secret() -> String {
+    let mut secret = [0u8; 20];
+    thread_rng().fill_bytes(&mut secret);
+    Secret::Raw(secret.to_vec()).to_encoded().to_string()
+}
+
+/// The codes of `username`'s authenticator, as SHA-1, six digits, every
+/// 30 seconds: the defaults every authenticator app supports.
+pub fn totp(secret: &str, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {:?}", e))?;
+    // Unchecked: the username can contain anything, and gets escaped in the URI
+    Ok(TOTP::new_unchecked(
+        Algorithm::SHA1,
+        6,
+        0,
+        TOTP_STEP,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        username.to_string(),
+    ))
+}
+
+/// The time step `code` belongs to, if it is valid now. The previous and next
+/// steps are accepted too, for clocks that are a little off.
+pub fn matching_time_step(totp: &TOTP, code: &str) -> Result<Option<i64>, anyhow::Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("The system clock is set before 1970")?
+        .as_secs();
+    let current_step = now / TOTP_STEP;
+    let step = [current_step - 1, current_step, current_step + 1]
+        .into_iter()
+        .find(|step| totp.check(code.trim(), step * TOTP_STEP));
+    Ok(step.map(|step| step as i64))
+}
+
+/// Whether `user_id` has to give a second factor to log in.
+#[tracing::instrument(name = "Check if two-factor authentication is on", skip(pool))]
+pub async fn has_two_factor(user_id: Uuid, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret IS NOT NULL AS "enabled!" FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check if a user has two-factor authentication on.")?;
+    Ok(row.enabled)
+}
+
+/// Check a code from `user_id`'s authenticator, or one of their recovery
+/// codes. Either works only once.
+#[tracing::instrument(name = "Verify second factor", skip(code, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let user = sqlx::query!(
+        "SELECT username, totp_secret FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the TOTP secret of a user.")?;
+    let Some(secret) = user.totp_secret else {
+        return Ok(false);
+    };
+
+    let totp = totp(&secret, &user.username)?;
+    if let Some(step) = matching_time_step(&totp, code)? {
+        // Someone watching over the user's shoulder cannot reuse their code
+        let n_updated = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $2
+            WHERE user_id = $1
+                AND (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the use of a TOTP code.")?
+        .rows_affected();
+        return Ok(n_updated == 1);
+    }
+
+    let Ok(recovery_code) = RecoveryCode::parse(code) else {
+        return Ok(false);
+    };
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        recovery_code.hash(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use a recovery code.")?
+    .rows_affected();
+    if n_updated == 1 {
+        tracing::info!("A recovery code has been used");
+    }
+    Ok(n_updated == 1)
+}
+
+/// Throw away the recovery codes of `user_id` and store new ones, which can
+/// only be shown to them now.
+#[tracing::instrument(name = "Replace recovery codes", skip(transaction))]
+pub async fn replace_recovery_codes(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<RecoveryCode>, anyhow::Error> {
+    let query = sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id);
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete previous recovery codes.")?;
+    let codes: Vec<RecoveryCode> = std::iter::repeat_with(RecoveryCode::generate)
+        .take(RECOVERY_CODE_COUNT)
+        .collect();
+    let code_hashes: Vec<String> = codes.iter().map(RecoveryCode::hash).collect();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO recovery_codes (user_id, code_hash)
+        SELECT $1, code_hash FROM UNNEST($2::TEXT[]) AS code_hash
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        &code_hashes,
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to store recovery codes.")?;
+    Ok(codes)
+}
+
+/// Turn two-factor authentication off for `user_id`, for instance when they
+/// lost both their authenticator and their recovery codes.
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(user_id: Uuid, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let query = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1 AND totp_secret IS NOT NULL
+        "#,
+        user_id
+    );
+    let n_updated = transaction
+        .execute(query)
+        .await
+        .context("Failed to remove the TOTP secret of a user.")?
+        .rows_affected();
+    let query = sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id);
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the recovery codes of a user.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor authentication")?;
+    Ok(n_updated == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_some};
+
+    use super::{generate_totp_secret, matching_time_step, totp};
+
+    #[test]
+    fn the_current_code_matches() {
+        let totp = totp(&generate_totp_secret(), "user:name").unwrap();
+        let code = totp.generate_current().unwrap();
+        assert_some!(matching_time_step(&totp, &code).unwrap());
+    }
+
+    #[test]
+    fn codes_of_the_wrong_length_do_not_match() {
+        let totp = totp(&generate_totp_secret(), "username").unwrap();
+        let code = totp.generate_current().unwrap();
+        assert_none!(matching_time_step(&totp, &code[1..]).unwrap());
+    }
+}
@@ -0,0 +1,82 @@
+use std::net::IpAddr;
+
+use actix_web::HttpRequest;
+
+/// The reverse proxies in front of the application. Only they are trusted to
+/// tell us who the client is, through `X-Forwarded-For`: anyone else could
+/// put whatever they like in that header.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// The address of the client behind `request`, e.g. to rate limit it.
+    ///
+    /// That is the peer address, unless the peer is a trusted proxy: we then
+    /// walk `X-Forwarded-For` from the right, each proxy appending the address
+    /// it got the request from, and stop at the first address we do not trust.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let mut client_ip = request.peer_addr()?.ip();
+        if !self.0.contains(&client_ip) {
+            return Some(client_ip);
+        }
+        let forwarded_for = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>();
+        for hop in forwarded_for.into_iter().rev() {
+            match hop.trim().parse() {
+                Ok(ip) => client_ip = ip,
+                // Not written by a proxy of ours: stick to the last trusted hop
+                Err(_) => break,
+            }
+            if !self.0.contains(&client_ip) {
+                break;
+            }
+        }
+        Some(client_ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrustedProxies;
+    use actix_web::test::TestRequest;
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_headers_are_ignored_without_trusted_proxies() {
+        let request = TestRequest::default()
+            .peer_addr("203.0.113.7:4321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.1"))
+            .to_http_request();
+        let client_ip = TrustedProxies::default().client_ip(&request);
+        assert_eq!(client_ip, Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn the_last_untrusted_hop_is_the_client() {
+        let proxies = TrustedProxies(vec![ip("10.0.0.1"), ip("10.0.0.2")]);
+        // The client made up the first entry
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.1, 203.0.113.7, 10.0.0.2"))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&request), Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn garbage_in_the_header_falls_back_to_the_last_trusted_hop() {
+        let proxies = TrustedProxies(vec![ip("10.0.0.1")]);
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "not-an-ip"))
+            .to_http_request();
+        assert_eq!(proxies.client_ip(&request), Some(ip("10.0.0.1")));
+    }
+}
@@ -2,35 +2,83 @@ use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::sync::Arc;
 
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailSender, OutboxEmailClient, PostmarkEmailClient, SmtpEmailClient};
+use crate::rate_limiter::RateLimiter;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub worker: WorkerSettings,
+    pub subscriptions: SubscriptionSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub password_hashing: PasswordHashingSettings,
     pub redis_uri: Secret<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    pub provider: EmailProvider,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub smtp: SmtpSettings,
+    pub outbox_directory: String,
 }
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    /// Postmark's HTTP API, at `base_url`.
+    Postmark,
+    Smtp,
+    /// Write emails to `outbox_directory` instead of sending them.
+    Outbox,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub security: SmtpSecurity,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain text, only suitable for a relay running on the same host.
+    None,
+    StartTls,
+    /// TLS from the start of the connection (usually port 465).
+    Tls,
+}
+
 impl EmailClientSettings {
-    pub fn client(self) -> EmailClient {
+    pub fn client(self) -> Arc<dyn EmailSender> {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        match self.provider {
+            EmailProvider::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailProvider::Smtp => {
+                Arc::new(SmtpEmailClient::new(&self.smtp, sender_email, timeout))
+            }
+            EmailProvider::Outbox => {
+                Arc::new(OutboxEmailClient::new(self.outbox_directory, sender_email))
+            }
+        }
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -41,6 +89,118 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct WorkerSettings {
+    pub max_retries: u16,
+    pub min_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+    pub poll_interval_seconds: u64,
+    pub concurrency: u32,
+    pub messages_per_second: u32,
+    pub burst_size: u32,
+    /// How many deliveries a task hands over to the email provider at once.
+    pub batch_size: u32,
+}
+
+impl WorkerSettings {
+    pub fn min_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.min_backoff_milliseconds)
+    }
+    pub fn max_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.max_backoff_milliseconds)
+    }
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.poll_interval_seconds)
+    }
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.messages_per_second, self.burst_size)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionSettings {
+    /// How long a confirmation link stays valid.
+    pub confirmation_token_ttl_hours: u64,
+    /// How long we keep people who never confirmed their subscription around.
+    pub unconfirmed_retention_days: u64,
+    pub purge_interval_seconds: u64,
+    pub rate_limit: RateLimitSettings,
+}
+
+/// Limits on `POST /subscriptions`: every request sends an email.
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub max_requests_per_ip: u64,
+    pub max_requests_per_email: u64,
+    pub window_seconds: u64,
+    /// Prepended to the Redis keys holding the counters.
+    pub key_prefix: String,
+}
+
+impl RateLimitSettings {
+    pub fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.window_seconds)
+    }
+}
+
+impl SubscriptionSettings {
+    pub fn confirmation_token_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.confirmation_token_ttl_hours * 60 * 60)
+    }
+    pub fn unconfirmed_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.unconfirmed_retention_days * 24 * 60 * 60)
+    }
+    pub fn purge_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.purge_interval_seconds)
+    }
+}
+
+/// Limits on failed attempts at `POST /login`: every attempt runs argon2.
+#[derive(serde::Deserialize, Clone)]
+pub struct LoginThrottleSettings {
+    /// Failed attempts allowed before we start making a subject wait.
+    pub free_failures: u64,
+    pub min_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+    pub max_failures_per_username: u64,
+    pub max_failures_per_ip: u64,
+    pub lockout_seconds: u64,
+    /// How long failed attempts are remembered.
+    pub window_seconds: u64,
+    /// Prepended to the Redis keys holding the counters.
+    pub key_prefix: String,
+}
+
+impl LoginThrottleSettings {
+    pub fn min_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.min_delay_milliseconds)
+    }
+    pub fn max_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.max_delay_milliseconds)
+    }
+    pub fn lockout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.lockout_seconds)
+    }
+    pub fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.window_seconds)
+    }
+}
+
+/// The argon2id parameters new password hashes are computed with. Hashes
+/// computed with other parameters are upgraded when their owner logs in.
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordHashingSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub username: String,
@@ -137,4 +297,15 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    pub shutdown_timeout_seconds: u64,
+    /// Reverse proxies allowed to tell us the client address in
+    /// `X-Forwarded-For`.
+    #[serde(default)]
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_seconds)
+    }
 }
@@ -1,8 +1,14 @@
 mod new_subscriber;
+mod random_token;
+mod recovery_code;
 mod subscriber_email;
 mod subscriber_name;
-mod subscription_token;
+mod subscription_status;
 pub use new_subscriber::NewSubscriber;
+pub use random_token::{
+    InvitationToken, PasswordResetToken, RandomToken, SubscriptionToken, UnsubscribeToken,
+};
+pub use recovery_code::RecoveryCode;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
-pub use subscription_token::SubscriptionToken;
+pub use subscription_status::{StatusChange, SubscriptionStatus};
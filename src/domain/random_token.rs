@@ -0,0 +1,89 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// A random alphanumeric token of `LENGTH` characters, e.g. sent in a link.
+#[derive(Debug)]
+pub struct RandomToken<const LENGTH: usize>(String);
+
+/// Confirms a subscription.
+pub type SubscriptionToken = RandomToken<25>;
+/// Lets subscribers unsubscribe from every email they receive.
+pub type UnsubscribeToken = RandomToken<25>;
+/// Sent to people invited to the admin area, so they can choose a password.
+/// Only its hash is stored.
+pub type InvitationToken = RandomToken<32>;
+/// Emailed to users who forgot their password. Only its hash is stored.
+pub type PasswordResetToken = RandomToken<32>;
+
+impl<const LENGTH: usize> RandomToken<LENGTH> {
+    pub fn parse(s: String) -> Result<Self, String> {
+        if s.len() == LENGTH && s.chars().all(|c| c.is_ascii_alphanumeric()) {
+            Ok(Self(s))
+        } else {
+            Err(format!("Invalid Token: {}.", s))
+        }
+    }
+
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(LENGTH)
+            .collect();
+        Self(token)
+    }
+
+    /// What we store in place of tokens that must not be usable by whoever
+    /// reads the database.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl<const LENGTH: usize> AsRef<str> for RandomToken<LENGTH> {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::{InvitationToken, SubscriptionToken};
+
+    #[test]
+    fn generated_token_is_valid() {
+        let token = SubscriptionToken::generate();
+        assert_eq!(token.as_ref().len(), 25);
+        assert_ok!(SubscriptionToken::parse(token.as_ref().into()));
+        let token = InvitationToken::generate();
+        assert_eq!(token.as_ref().len(), 32);
+        assert_ok!(InvitationToken::parse(token.as_ref().into()));
+    }
+
+    #[test]
+    fn token_of_the_wrong_length_is_error() {
+        assert_err!(SubscriptionToken::parse("x".into()));
+        assert_err!(SubscriptionToken::parse("x".repeat(26)));
+        assert_err!(InvitationToken::parse("x".repeat(31)));
+        assert_err!(InvitationToken::parse("x".repeat(33)));
+    }
+
+    #[test]
+    fn token_with_non_alphanumeric_char_is_error() {
+        let token = "xxxxxxxxxxxx^xxxxxxxxxxxx";
+        assert_err!(SubscriptionToken::parse(token.into()));
+        // Multi-byte characters do not make up for missing ones
+        let token = "xxxxxxxxxxxxéxxxxxxxxxx";
+        assert_err!(SubscriptionToken::parse(token.into()));
+    }
+
+    #[test]
+    fn the_hash_does_not_reveal_the_token() {
+        let token = InvitationToken::generate();
+        assert_ne!(token.hash(), token.as_ref());
+        assert_eq!(token.hash(), token.hash());
+        assert_err!(InvitationToken::parse(token.hash()));
+    }
+}
@@ -0,0 +1,62 @@
+use rand::{seq::SliceRandom, thread_rng};
+use sha2::{Digest, Sha256};
+
+const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+const LENGTH: usize = 10;
+
+/// Logs in once in place of a two-factor code, for users who lost their
+/// authenticator. Only its hash is stored.
+#[derive(Debug)]
+pub struct RecoveryCode(String);
+
+impl RecoveryCode {
+    /// Case and surrounding whitespace do not matter: codes are typed by hand.
+    pub fn parse(s: &str) -> Result<RecoveryCode, String> {
+        let s = s.trim().to_lowercase();
+        if s.len() == LENGTH && s.bytes().all(|c| CHARSET.contains(&c)) {
+            Ok(Self(s))
+        } else {
+            Err(format!("Invalid recovery code: {}.", s))
+        }
+    }
+
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        let code = std::iter::repeat_with(|| *CHARSET.choose(&mut rng).unwrap())
+            .map(char::from)
+            .take(LENGTH)
+            .collect();
+        Self(code)
+    }
+
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl AsRef<str> for RecoveryCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::RecoveryCode;
+
+    #[test]
+    fn generated_code_is_valid() {
+        let code = RecoveryCode::generate();
+        assert_ok!(RecoveryCode::parse(code.as_ref()));
+    }
+
+    #[test]
+    fn codes_are_parsed_regardless_of_case_and_whitespace() {
+        let code = RecoveryCode::generate();
+        let typed = format!(" {} ", code.as_ref().to_uppercase());
+        assert_eq!(RecoveryCode::parse(&typed).unwrap().hash(), code.hash());
+        assert_err!(RecoveryCode::parse(&code.hash()));
+    }
+}
@@ -0,0 +1,105 @@
+/// Where a subscriber stands. Every status change goes through `transition_to`,
+/// which knows the moves that are allowed:
+///
+/// - pending_confirmation → confirmed, when they click the confirmation link;
+/// - pending_confirmation or confirmed → unsubscribed;
+/// - unsubscribed → pending_confirmation, when they subscribe again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+/// The outcome of asking for a status change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusChange {
+    Applied,
+    /// The subscriber was already in the requested status.
+    AlreadyApplied,
+    NotAllowed,
+}
+
+impl SubscriptionStatus {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            other => Err(format!("{} is not a valid subscription status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+        }
+    }
+
+    pub fn transition_to(self, next: SubscriptionStatus) -> StatusChange {
+        use SubscriptionStatus::*;
+        match (self, next) {
+            (current, next) if current == next => StatusChange::AlreadyApplied,
+            (PendingConfirmation, Confirmed)
+            | (PendingConfirmation, Unsubscribed)
+            | (Confirmed, Unsubscribed)
+            | (Unsubscribed, PendingConfirmation) => StatusChange::Applied,
+            _ => StatusChange::NotAllowed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{StatusChange, SubscriptionStatus};
+    use claims::{assert_err, assert_ok_eq};
+
+    const ALL: [SubscriptionStatus; 3] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+    ];
+
+    #[test]
+    fn statuses_round_trip_through_their_database_representation() {
+        for status in ALL {
+            assert_ok_eq!(SubscriptionStatus::parse(status.as_str()), status);
+        }
+        assert_err!(SubscriptionStatus::parse("deleted"));
+    }
+
+    #[test]
+    fn moving_to_the_current_status_is_a_no_op() {
+        for status in ALL {
+            assert_eq!(status.transition_to(status), StatusChange::AlreadyApplied);
+        }
+    }
+
+    #[test]
+    fn an_unsubscribed_person_must_subscribe_again_before_confirming() {
+        let status = SubscriptionStatus::Unsubscribed;
+        assert_eq!(
+            status.transition_to(SubscriptionStatus::Confirmed),
+            StatusChange::NotAllowed
+        );
+        assert_eq!(
+            status.transition_to(SubscriptionStatus::PendingConfirmation),
+            StatusChange::Applied
+        );
+    }
+
+    #[test]
+    fn a_confirmed_subscriber_cannot_go_back_to_pending() {
+        let status = SubscriptionStatus::Confirmed;
+        assert_eq!(
+            status.transition_to(SubscriptionStatus::PendingConfirmation),
+            StatusChange::NotAllowed
+        );
+        assert_eq!(
+            status.transition_to(SubscriptionStatus::Unsubscribed),
+            StatusChange::Applied
+        );
+    }
+}
@@ -0,0 +1,99 @@
+mod outbox;
+mod postmark;
+mod smtp;
+pub use outbox::OutboxEmailClient;
+pub use postmark::PostmarkEmailClient;
+pub use smtp::SmtpEmailClient;
+
+use std::time::Duration;
+
+use crate::domain::SubscriberEmail;
+use crate::utils::error_chain_fmt;
+
+/// The largest batch `EmailSender::send_email_batch` accepts, Postmark's limit.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// Anything able to deliver an email on our behalf: the newsletter does not
+/// care which provider is on the other end.
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError>;
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), SendEmailError> {
+        self.send(&Email {
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_url: None,
+        })
+        .await
+    }
+
+    /// Send up to `MAX_BATCH_SIZE` emails, returning one outcome per email in
+    /// the same order. `Err` means that the batch as a whole failed.
+    /// Providers without a batch API send them one at a time.
+    async fn send_email_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), SendEmailError>>, SendEmailError> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            outcomes.push(self.send(email).await);
+        }
+        Ok(outcomes)
+    }
+}
+
+pub struct Email<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    /// Where the recipient can unsubscribe from, advertised through the
+    /// `List-Unsubscribe` headers (RFC 8058) that let mail clients do it in one click.
+    pub unsubscribe_url: Option<&'a str>,
+}
+
+impl Email<'_> {
+    /// The headers, as (name, value) pairs, to add on top of the usual ones.
+    pub fn extra_headers(&self) -> Vec<(&'static str, String)> {
+        match self.unsubscribe_url {
+            Some(url) => vec![
+                ("List-Unsubscribe", format!("<{}>", url)),
+                (
+                    "List-Unsubscribe-Post",
+                    "List-Unsubscribe=One-Click".to_string(),
+                ),
+            ],
+            None => vec![],
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SendEmailError {
+    #[error("The email provider is throttling our requests")]
+    RateLimited {
+        /// How long the email provider asked us to wait, if it said so.
+        retry_after: Option<Duration>,
+    },
+    /// The provider refused the message itself (e.g. an inactive or malformed
+    /// recipient) - sending it again will not change the outcome.
+    #[error("The email provider rejected the message")]
+    Rejected(#[source] anyhow::Error),
+    #[error("Failed to send the email")]
+    Failed(#[source] anyhow::Error),
+}
+
+impl std::fmt::Debug for SendEmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
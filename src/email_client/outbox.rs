@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::smtp::build_message;
+use super::{Email, EmailSender, SendEmailError};
+use crate::domain::SubscriberEmail;
+
+/// Writes every email to `directory` as an `.eml` file instead of sending it.
+/// Handy in local development: no provider account or mock server required.
+pub struct OutboxEmailClient {
+    directory: PathBuf,
+    sender: SubscriberEmail,
+}
+
+impl OutboxEmailClient {
+    pub fn new(directory: impl Into<PathBuf>, sender: SubscriberEmail) -> Self {
+        Self {
+            directory: directory.into(),
+            sender,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for OutboxEmailClient {
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+        let message = build_message(&self.sender, email)?;
+        // Timestamp first, so that listing the directory shows emails in the
+        // order they were sent
+        let file_name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+            Uuid::new_v4()
+        );
+        let write = async {
+            tokio::fs::create_dir_all(&self.directory)
+                .await
+                .context("Failed to create the outbox directory")?;
+            tokio::fs::write(self.directory.join(file_name), message.formatted())
+                .await
+                .context("Failed to write the email to the outbox")
+        };
+        write.await.map_err(SendEmailError::Failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailSender, OutboxEmailClient};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use uuid::Uuid;
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_one_file_per_email_to_the_outbox() {
+        // Arrange
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_client = OutboxEmailClient::new(&directory, email());
+        let recipient = email();
+
+        // Act
+        email_client
+            .send_email(&recipient, "Welcome!", "<p>Hi!</p>", "Hi!")
+            .await
+            .unwrap();
+        email_client
+            .send_email(&recipient, "Second issue", "<p>Hi!</p>", "Hi!")
+            .await
+            .unwrap();
+
+        // Assert
+        let mut files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        let emails = files
+            .iter()
+            .map(|file| std::fs::read_to_string(file).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(emails.len(), 2);
+        assert!(emails[0].contains(&format!("To: {}", recipient.as_ref())));
+        assert!(emails[0].contains("Subject: Welcome!"));
+        assert!(emails[1].contains("Subject: Second issue"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn newsletter_emails_carry_the_unsubscribe_headers() {
+        // Arrange
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_client = OutboxEmailClient::new(&directory, email());
+
+        // Act
+        email_client
+            .send(&Email {
+                recipient: &email(),
+                subject: "Newsletter",
+                html_content: "<p>Hi!</p>",
+                text_content: "Hi!",
+                unsubscribe_url: Some("https://my-newsletter.com/unsubscribe"),
+            })
+            .await
+            .unwrap();
+
+        // Assert
+        let file = std::fs::read_dir(&directory).unwrap().next().unwrap();
+        let email = std::fs::read_to_string(file.unwrap().path()).unwrap();
+        assert!(email.contains("List-Unsubscribe: <https://my-newsletter.com/unsubscribe>"));
+        assert!(email.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
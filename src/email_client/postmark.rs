@@ -0,0 +1,426 @@
+use std::time::Duration;
+
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+
+use super::{Email, EmailSender, SendEmailError, MAX_BATCH_SIZE};
+use crate::domain::SubscriberEmail;
+
+pub struct PostmarkEmailClient {
+    http_client: Client,
+    base_url: reqwest::Url,
+    sender: SubscriberEmail,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self {
+            http_client: Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("Error initialising email http client"),
+            base_url: reqwest::Url::parse(&base_url).expect("Invalid base Url"),
+            sender,
+            authorization_token,
+        }
+    }
+}
+
+impl PostmarkEmailClient {
+    /// Postmark answers with 200 as long as the request itself is valid.
+    async fn post<Body: serde::Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &Body,
+    ) -> Result<reqwest::Response, SendEmailError> {
+        let url = self.base_url.join(path).expect("Could not create mail url");
+        let response = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| SendEmailError::Failed(e.into()))?;
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(SendEmailError::RateLimited {
+                retry_after: parse_retry_after(response.headers()),
+            });
+        }
+        response.error_for_status().map_err(|e| {
+            // Request timeouts are the only 4xx worth trying again
+            if status.is_client_error() && status != StatusCode::REQUEST_TIMEOUT {
+                SendEmailError::Rejected(e.into())
+            } else {
+                SendEmailError::Failed(e.into())
+            }
+        })
+    }
+
+    fn request<'a>(&'a self, email: &Email<'a>) -> SendEmailRequest<'a> {
+        SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: email.recipient.as_ref(),
+            subject: email.subject,
+            html_body: email.html_content,
+            text_body: email.text_content,
+            headers: email
+                .extra_headers()
+                .into_iter()
+                .map(|(name, value)| EmailHeader { name, value })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for PostmarkEmailClient {
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+        self.post("email", &self.request(email)).await?;
+
+        Ok(())
+    }
+
+    async fn send_email_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), SendEmailError>>, SendEmailError> {
+        if emails.len() > MAX_BATCH_SIZE {
+            return Err(SendEmailError::Rejected(anyhow::anyhow!(
+                "Postmark accepts at most {} emails per batch, got {}",
+                MAX_BATCH_SIZE,
+                emails.len()
+            )));
+        }
+        let request_body: Vec<_> = emails.iter().map(|email| self.request(email)).collect();
+        let results: Vec<SendEmailResponse> = self
+            .post("email/batch", &request_body)
+            .await?
+            .json()
+            .await
+            .map_err(|e| SendEmailError::Failed(e.into()))?;
+        if results.len() != emails.len() {
+            return Err(SendEmailError::Failed(anyhow::anyhow!(
+                "Postmark returned {} results for a batch of {} emails",
+                results.len(),
+                emails.len()
+            )));
+        }
+        // Problems with a single message (e.g. 406 - inactive recipient) are
+        // reported through its error code, never through the HTTP status.
+        Ok(results
+            .into_iter()
+            .map(|result| match result.error_code {
+                0 => Ok(()),
+                error_code => Err(SendEmailError::Rejected(anyhow::anyhow!(
+                    "Postmark error {}: {}",
+                    error_code,
+                    result.message
+                ))),
+            })
+            .collect())
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let retry_at = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (retry_at.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader {
+    name: &'static str,
+    value: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    error_code: i64,
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailSender, PostmarkEmailClient, SendEmailError};
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Try to parse the body as a JSON value
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // Check that all the mandatory fields are populated
+                // without inspecting the field values
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                // If parsing failed, do not match the request
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            // Not a 200 anymore!
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_is_rejected_if_the_server_returns_422() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(matches!(outcome, Err(SendEmailError::Rejected(_))));
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        let response = ResponseTemplate::new(200)
+            // 3 minutes!
+            .set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_how_long_to_wait_if_the_server_returns_429() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        match outcome {
+            Err(SendEmailError::RateLimited { retry_after }) => {
+                assert_eq!(retry_after, Some(std::time::Duration::from_secs(7)))
+            }
+            _ => panic!("Expected the request to be rate limited"),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_sends_every_email_in_a_single_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let recipients = [email(), email(), email()];
+        let (subject, content) = (subject(), content());
+        let emails: Vec<_> = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                unsubscribe_url: Some("https://my-newsletter.com/unsubscribe"),
+            })
+            .collect();
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK"},
+                {"ErrorCode": 406, "Message": "Inactive recipient"},
+                {"ErrorCode": 0, "Message": "OK"},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcomes = email_client(mock_server.uri())
+            .send_email_batch(&emails)
+            .await
+            .unwrap();
+
+        // Assert
+        let body: serde_json::Value =
+            serde_json::from_slice(&mock_server.received_requests().await.unwrap()[0].body)
+                .unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        assert_eq!(body[1]["To"], recipients[1].as_ref());
+        assert_eq!(
+            body[1]["Headers"],
+            serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://my-newsletter.com/unsubscribe>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+            ])
+        );
+        assert_eq!(outcomes.len(), 3);
+        assert_ok!(&outcomes[0]);
+        assert!(matches!(outcomes[1], Err(SendEmailError::Rejected(_))));
+        assert_ok!(&outcomes[2]);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_as_a_whole_if_the_server_returns_429() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let (recipient, subject, content) = (email(), subject(), content());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client(mock_server.uri())
+            .send_email_batch(&[Email {
+                recipient: &recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                unsubscribe_url: None,
+            }])
+            .await;
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(SendEmailError::RateLimited { retry_after: None })
+        ));
+    }
+}
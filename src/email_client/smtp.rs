@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::ExposeSecret;
+
+use super::{Email, EmailSender, SendEmailError};
+use crate::configuration::{SmtpSecurity, SmtpSettings};
+use crate::domain::SubscriberEmail;
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    pub fn new(settings: &SmtpSettings, sender: SubscriberEmail, timeout: Duration) -> Self {
+        let builder = match settings.security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host),
+            SmtpSecurity::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+            }
+            SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &settings.host,
+            )),
+        }
+        .expect("Error initialising the SMTP transport");
+        let mut builder = builder.port(settings.port).timeout(Some(timeout));
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().clone(),
+            ));
+        }
+        Self {
+            transport: builder.build(),
+            sender,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpEmailClient {
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+        let message = build_message(&self.sender, email)?;
+        self.transport.send(message).await.map_err(|e| {
+            // 5xx replies are final, everything else (4xx replies, timeouts,
+            // connection errors) might go through on the next attempt
+            if e.is_permanent() {
+                SendEmailError::Rejected(e.into())
+            } else {
+                SendEmailError::Failed(e.into())
+            }
+        })?;
+        Ok(())
+    }
+}
+
+/// A multipart/alternative message carrying both versions of the content.
+pub(super) fn build_message(
+    sender: &SubscriberEmail,
+    email: &Email<'_>,
+) -> Result<Message, SendEmailError> {
+    let mailbox = |email: &SubscriberEmail| {
+        email
+            .as_ref()
+            .parse::<Mailbox>()
+            .map_err(|e| SendEmailError::Rejected(e.into()))
+    };
+    let mut message = Message::builder()
+        .from(mailbox(sender)?)
+        .to(mailbox(email.recipient)?)
+        .subject(email.subject)
+        .multipart(MultiPart::alternative_plain_html(
+            email.text_content.to_owned(),
+            email.html_content.to_owned(),
+        ))
+        .map_err(|e| SendEmailError::Rejected(e.into()))?;
+    for (name, value) in email.extra_headers() {
+        message.headers_mut().insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str(name),
+            value,
+        ));
+    }
+    Ok(message)
+}
@@ -1,42 +1,242 @@
 use std::time::Duration;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
-    startup::get_connection_pool,
+    configuration::{Settings, WorkerSettings},
+    domain::{
+        NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus, SubscriptionToken,
+    },
+    email_client::{Email, EmailSender, SendEmailError, MAX_BATCH_SIZE},
+    rate_limiter::RateLimiter,
+    routes::send_confirmation_email,
 };
+use rand::Rng;
+use sqlx::postgres::{PgListener, PgPoolOptions};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::Span;
 use uuid::Uuid;
 
+/// Postgres channel used to announce that new tasks have been enqueued.
+pub const ISSUE_DELIVERY_CHANNEL: &str = "issue_delivery_queue";
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i32,
+    unsubscribe_token: Option<String>,
+}
+
+struct ConfirmationTask {
+    subscription_token: String,
+    n_retries: i32,
+    email: String,
+    name: String,
+    status: String,
+}
 
+/// Runs the delivery tasks until `shutdown` is cancelled. Tasks finish the delivery
+/// they are working on, stop dequeuing and we return once all of them are done.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let settings = configuration.worker;
+    let concurrency = settings.concurrency.max(1);
+    // Every delivery task holds on to one connection for the lifetime of its
+    // transaction, the notification listener keeps one more for itself.
+    let connection_pool = PgPoolOptions::new()
+        .max_connections(concurrency + 1)
+        .connect_lazy_with(configuration.database.with_db());
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let rate_limiter = Arc::new(settings.rate_limiter());
+
+    let mut listener = PgListener::connect_with(&connection_pool).await?;
+    listener.listen(ISSUE_DELIVERY_CHANNEL).await?;
+    let (wake_up, new_tasks) = watch::channel(());
+
+    let mut workers = JoinSet::new();
+    for _ in 0..concurrency {
+        workers.spawn(worker_loop(
+            connection_pool.clone(),
+            email_client.clone(),
+            rate_limiter.clone(),
+            settings.clone(),
+            configuration.application.base_url.clone(),
+            new_tasks.clone(),
+            shutdown.clone(),
+        ));
+    }
+    tokio::select! {
+        _ = forward_notifications(listener, wake_up) => {}
+        _ = shutdown.cancelled() => {}
+        Some(outcome) = workers.join_next() => outcome??,
+    }
+    while let Some(outcome) = workers.join_next().await {
+        outcome??;
+    }
+    Ok(())
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    rate_limiter: Arc<RateLimiter>,
+    settings: WorkerSettings,
+    base_url: String,
+    mut new_tasks: watch::Receiver<()>,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        // Anything announced from now on might not be picked up by this attempt
+        new_tasks.borrow_and_update();
+        let outcome = try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            &rate_limiter,
+            &settings,
+            &base_url,
+            &shutdown,
+        )
+        .await;
+        match outcome {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                // We keep polling as a fallback: retries become due without any
+                // notification and notifications sent while the listener is
+                // reconnecting are lost.
+                tokio::select! {
+                    Ok(()) = new_tasks.changed() => {}
+                    _ = tokio::time::sleep(settings.poll_interval()) => {}
+                    _ = shutdown.cancelled() => {}
+                }
             }
             Err(_) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+            }
+            Ok(ExecutionOutcome::TaskCompleted | ExecutionOutcome::Stopped) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Wake up all idle delivery tasks whenever new tasks are announced on
+/// `ISSUE_DELIVERY_CHANNEL`.
+async fn forward_notifications(mut listener: PgListener, wake_up: watch::Sender<()>) {
+    loop {
+        match listener.recv().await {
+            Ok(_) => {
+                wake_up.send_replace(());
+            }
+            Err(e) => {
+                tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to receive a notification from the issue delivery channel",
+                );
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
         }
     }
 }
 
+/// Wake up idle workers. When called within a transaction, the notification is
+/// only delivered once the transaction commits.
+pub async fn notify_workers<'c, E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    executor
+        .execute(format!("NOTIFY {}", ISSUE_DELIVERY_CHANNEL).as_str())
+        .await?;
+    Ok(())
+}
+
+/// Enqueue a delivery of the issue for every confirmed subscriber.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        WITH enqueued AS (
+            INSERT INTO issue_delivery_queue (
+                newsletter_issue_id,
+                subscriber_email
+            )
+            SELECT $1, email
+            FROM subscriptions
+            WHERE status = 'confirmed'
+            RETURNING subscriber_email
+        )
+        UPDATE newsletter_issues
+        SET
+            n_enqueued = n_enqueued + (SELECT count(*) FROM enqueued),
+            queued_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    );
+    transaction.execute(query).await?;
+    notify_workers(&mut **transaction).await?;
+    Ok(())
+}
+
+/// Queue a confirmation email for the delivery worker, e.g. for an imported
+/// subscriber. Call `notify_workers` once the batch is queued.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &SubscriptionToken,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"INSERT INTO confirmation_email_queue (subscription_token) VALUES ($1)"#,
+        subscription_token.as_ref(),
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Publish the scheduled issues that are due.
+#[tracing::instrument(skip_all)]
+async fn publish_due_issues(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Concurrent callers wait on each other's row locks and skip the issues
+    // that are no longer scheduled by the time they get them.
+    let due_issues = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published'
+        WHERE status = 'scheduled' AND published_at <= now()
+        RETURNING newsletter_issue_id
+        "#,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    for issue in &due_issues {
+        enqueue_delivery_tasks(&mut transaction, issue.newsletter_issue_id).await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+async fn get_issue(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+) -> Result<NewsletterIssue, anyhow::Error> {
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
@@ -47,7 +247,7 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
         "#,
         issue_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut **transaction)
     .await?;
     Ok(issue)
 }
@@ -55,95 +255,424 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    /// `shutdown` was cancelled while we were waiting for the rate limiter.
+    Stopped,
 }
 
-#[tracing::instrument(
-    skip_all,
-    fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
-    ),
-    err
-)]
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
+    rate_limiter: &RateLimiter,
+    settings: &WorkerSettings,
+    base_url: &str,
+    shutdown: &CancellationToken,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    // Every time around, not only once the queue is empty: a large issue
+    // still sending does not hold back the scheduled ones
+    publish_due_issues(pool).await?;
+    let batch_size = settings.batch_size.clamp(1, MAX_BATCH_SIZE as u32);
+    // Waiting before dequeuing: no rows stay locked while we are held back
+    let batch_size = tokio::select! {
+        n_tokens = rate_limiter.acquire(batch_size) => n_tokens,
+        _ = shutdown.cancelled() => return Ok(ExecutionOutcome::Stopped),
+    };
+    // Confirmation emails go first: someone is waiting for each of them
+    let n_confirmations = send_queued_confirmation_emails(
+        pool,
+        email_client,
+        rate_limiter,
+        settings,
+        base_url,
+        batch_size,
+    )
+    .await?;
+    if n_confirmations > 0 {
+        rate_limiter.release(batch_size - n_confirmations);
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let batch = dequeue_tasks(pool, batch_size).await?;
+    let n_dequeued = batch.as_ref().map_or(0, |(_, tasks)| tasks.len() as u32);
+    rate_limiter.release(batch_size - n_dequeued);
+    if batch.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
+    let (mut transaction, tasks) = batch.unwrap();
+    Span::current().record("n_tasks", tasks.len());
+
+    let mut recipients = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => recipients.push((task, email)),
+            Err(e) => {
+                let e = anyhow::anyhow!(e);
                 tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Failed to deliver issue to a confirmed subscriber. \
-                Skipping.",
+                newsletter_issue_id = %task.newsletter_issue_id,
+                subscriber_email = %task.subscriber_email,
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid",
                 );
+                dead_letter_task(&mut transaction, &task, task.n_retries, &e).await?;
             }
         }
+    }
+    if recipients.is_empty() {
+        // Everything was dead-lettered: there is nothing left to send
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let mut issues = HashMap::new();
+    for (task, _) in &recipients {
+        if let Entry::Vacant(entry) = issues.entry(task.newsletter_issue_id) {
+            entry.insert(get_issue(&mut transaction, task.newsletter_issue_id).await?);
+        }
+    }
+    let unsubscribe_urls: Vec<_> = recipients
+        .iter()
+        .map(|(task, _)| {
+            task.unsubscribe_token.as_ref().map(|token| {
+                format!(
+                    "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+                    base_url, token
+                )
+            })
+        })
+        .collect();
+    let emails: Vec<_> = recipients
+        .iter()
+        .zip(&unsubscribe_urls)
+        .map(|((task, email), unsubscribe_url)| {
+            let issue = &issues[&task.newsletter_issue_id];
+            Email {
+                recipient: email,
+                subject: &issue.title,
+                html_content: &issue.html_content,
+                text_content: &issue.text_content,
+                unsubscribe_url: unsubscribe_url.as_deref(),
+            }
+        })
+        .collect();
+
+    match email_client.send_email_batch(&emails).await {
+        Ok(outcomes) => {
+            for ((task, _), outcome) in recipients.iter().zip(outcomes) {
+                record_outcome(
+                    &mut transaction,
+                    task,
+                    outcome.as_ref(),
+                    rate_limiter,
+                    settings,
+                )
+                .await?;
+            }
+        }
+        // Nothing went out: every delivery in the batch shares the same fate
+        Err(e) => {
+            for (task, _) in &recipients {
+                record_outcome(&mut transaction, task, Err(&e), rate_limiter, settings).await?;
+            }
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Send up to `max_emails` of the queued confirmation emails, one request each,
+/// and return how many were dequeued.
+#[tracing::instrument(skip_all)]
+async fn send_queued_confirmation_emails(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    rate_limiter: &RateLimiter,
+    settings: &WorkerSettings,
+    base_url: &str,
+    max_emails: u32,
+) -> Result<u32, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let tasks = sqlx::query_as!(
+        ConfirmationTask,
+        r#"
+        SELECT q.subscription_token, q.n_retries, s.email, s.name, s.status
+        FROM confirmation_email_queue q
+        JOIN subscription_tokens t USING (subscription_token)
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT $1
+        "#,
+        i64::from(max_emails),
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let mut throttled_for = None;
+    for task in &tasks {
+        if let Some(delay) = throttled_for {
+            // No point in trying the others before the provider lets us
+            postpone_confirmation(&mut transaction, task, delay).await?;
+            continue;
+        }
+        throttled_for = send_confirmation(
+            &mut transaction,
+            email_client,
+            rate_limiter,
+            settings,
+            base_url,
+            task,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(tasks.len() as u32)
+}
+
+/// Send one queued confirmation email. Returns how long to wait if the email
+/// provider is throttling us.
+#[tracing::instrument(
+    skip_all,
+    fields(subscriber_email = %task.email, n_retries = task.n_retries)
+)]
+async fn send_confirmation(
+    transaction: &mut PgTransaction,
+    email_client: &dyn EmailSender,
+    rate_limiter: &RateLimiter,
+    settings: &WorkerSettings,
+    base_url: &str,
+    task: &ConfirmationTask,
+) -> Result<Option<Duration>, anyhow::Error> {
+    // Confirmed or unsubscribed since: the link has nothing left to do
+    if task.status != SubscriptionStatus::PendingConfirmation.as_str() {
+        forget_confirmation(transaction, task).await?;
+        return Ok(None);
+    }
+    let parsed = SubscriberEmail::parse(task.email.clone()).and_then(|email| {
+        Ok((
+            NewSubscriber {
+                email,
+                name: SubscriberName::parse(task.name.clone())?,
+            },
+            SubscriptionToken::parse(task.subscription_token.clone())?,
+        ))
+    });
+    let (new_subscriber, token) = match parsed {
+        Ok(parsed) => parsed,
         Err(e) => {
             tracing::error!(
-            error.cause_chain = ?e,
             error.message = %e,
-            "Skipping a confirmed subscriber. \
-            Their stored contact details are invalid",
+            "Skipping a confirmation email. \
+            The stored subscriber details are invalid",
             );
+            forget_confirmation(transaction, task).await?;
+            return Ok(None);
+        }
+    };
+    let e = match send_confirmation_email(email_client, new_subscriber, base_url, &token).await {
+        Ok(()) => {
+            forget_confirmation(transaction, task).await?;
+            return Ok(None);
         }
+        Err(e) => e,
+    };
+    if let SendEmailError::RateLimited { retry_after } = e {
+        let delay = retry_after
+            .unwrap_or_else(|| backoff_delay(0, settings))
+            .min(settings.max_backoff());
+        tracing::warn!(
+            retry_after_seconds = delay.as_secs_f64(),
+            "The email provider is throttling us. Backing off.",
+        );
+        rate_limiter.pause_for(delay);
+        postpone_confirmation(transaction, task, delay).await?;
+        return Ok(Some(delay));
     }
-    delete_task(transaction, issue_id, &email).await?;
+    if matches!(e, SendEmailError::Rejected(_)) || task.n_retries >= i32::from(settings.max_retries)
+    {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to send a confirmation email. Giving up: \
+        they will have to subscribe again to get a new link.",
+        );
+        forget_confirmation(transaction, task).await?;
+    } else {
+        tracing::warn!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to send a confirmation email. Scheduling a retry.",
+        );
+        let delay = backoff_delay(task.n_retries, settings);
+        let query = sqlx::query!(
+            r#"
+        UPDATE confirmation_email_queue
+        SET
+        n_retries = n_retries + 1,
+        execute_after = now() + make_interval(secs => $2)
+        WHERE subscription_token = $1
+        "#,
+            task.subscription_token,
+            delay.as_secs_f64()
+        );
+        transaction.execute(query).await?;
+    }
+    Ok(None)
+}
 
-    Ok(ExecutionOutcome::TaskCompleted)
+#[tracing::instrument(skip_all)]
+async fn postpone_confirmation(
+    transaction: &mut PgTransaction,
+    task: &ConfirmationTask,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE confirmation_email_queue
+    SET execute_after = now() + make_interval(secs => $2)
+    WHERE subscription_token = $1
+    "#,
+        task.subscription_token,
+        delay.as_secs_f64()
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn forget_confirmation(
+    transaction: &mut PgTransaction,
+    task: &ConfirmationTask,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM confirmation_email_queue WHERE subscription_token = $1"#,
+        task.subscription_token
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=%task.newsletter_issue_id,
+        subscriber_email=%task.subscriber_email,
+        n_retries=task.n_retries
+    )
+)]
+async fn record_outcome(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    outcome: Result<&(), &SendEmailError>,
+    rate_limiter: &RateLimiter,
+    settings: &WorkerSettings,
+) -> Result<(), anyhow::Error> {
+    let e = match outcome {
+        Ok(()) => return complete_task(transaction, task).await,
+        Err(e) => e,
+    };
+    if let SendEmailError::RateLimited { retry_after } = e {
+        // Being throttled says nothing about this delivery: it goes
+        // back in the queue without using up one of its retries.
+        // Capped: a huge Retry-After would otherwise stall every task for hours
+        let delay = retry_after
+            .unwrap_or_else(|| backoff_delay(0, settings))
+            .min(settings.max_backoff());
+        tracing::warn!(
+            retry_after_seconds = delay.as_secs_f64(),
+            "The email provider is throttling us. Backing off.",
+        );
+        // Every task shares the same quota: hold all of them back
+        rate_limiter.pause_for(delay);
+        return postpone_task(transaction, task, delay).await;
+    }
+    if let SendEmailError::Rejected(_) = e {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "The email provider rejected the delivery of an issue \
+        to a confirmed subscriber. Giving up.",
+        );
+        dead_letter_task(transaction, task, task.n_retries + 1, e).await
+    } else if task.n_retries >= i32::from(settings.max_retries) {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to deliver issue to a confirmed subscriber. \
+        Giving up after exhausting all retries.",
+        );
+        dead_letter_task(transaction, task, task.n_retries + 1, e).await
+    } else {
+        tracing::warn!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to deliver issue to a confirmed subscriber. \
+        Scheduling a retry.",
+        );
+        let delay = backoff_delay(task.n_retries, settings);
+        retry_task(transaction, task, delay).await
+    }
+}
+
+/// Exponential backoff with "equal jitter": the delay doubles with every retry,
+/// capped at `max_backoff`, and a random amount of up to half of it is shaved off
+/// so that deliveries failing together do not all retry at the same instant.
+fn backoff_delay(n_retries: i32, settings: &WorkerSettings) -> Duration {
+    let exponent = n_retries.clamp(0, 31) as u32;
+    let delay = settings
+        .min_backoff()
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(settings.max_backoff());
+    let half = delay / 2;
+    let jitter = rand::thread_rng().gen_range(Duration::ZERO..=half);
+    delay - half + jitter
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+/// Lock up to `batch_size` due deliveries to confirmed subscribers. They stay locked,
+/// and invisible to other workers, until the returned transaction is committed.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    batch_size: u32,
+) -> Result<Option<(PgTransaction, Vec<DeliveryTask>)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    let tasks = sqlx::query_as!(
+        DeliveryTask,
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            t.unsubscribe_token AS "unsubscribe_token?"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        JOIN subscriptions s ON s.email = q.subscriber_email
+        LEFT JOIN unsubscribe_tokens t ON t.subscriber_id = s.id
+        WHERE
+            q.execute_after <= now()
+            AND i.status = 'published'
+            AND s.status = 'confirmed'
+        FOR UPDATE OF q
         SKIP LOCKED
-        LIMIT 1
+        LIMIT $1
         "#,
+        i64::from(batch_size),
     )
-    .fetch_optional(&mut *transaction)
+    .fetch_all(&mut *transaction)
     .await?;
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
+    if tasks.is_empty() {
         Ok(None)
+    } else {
+        Ok(Some((transaction, tasks)))
     }
 }
+
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
+async fn complete_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
 ) -> Result<(), anyhow::Error> {
     let query = sqlx::query!(
         r#"
@@ -152,10 +681,168 @@ async fn delete_task(
     newsletter_issue_id = $1 AND
     subscriber_email = $2
     "#,
-        issue_id,
-        email
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO sent_deliveries (newsletter_issue_id, subscriber_email, sent_at)
+    VALUES ($1, $2, now())
+    "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    UPDATE newsletter_issues
+    SET
+    n_sent = n_sent + 1,
+    first_sent_at = COALESCE(first_sent_at, now()),
+    last_sent_at = now()
+    WHERE newsletter_issue_id = $1
+    "#,
+        task.newsletter_issue_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn retry_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE issue_delivery_queue
+    SET
+    n_retries = n_retries + 1,
+    execute_after = now() + make_interval(secs => $3)
+    WHERE
+    newsletter_issue_id = $1 AND
+    subscriber_email = $2
+    "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        delay.as_secs_f64()
     );
     transaction.execute(query).await?;
-    transaction.commit().await?;
     Ok(())
 }
+
+#[tracing::instrument(skip_all)]
+async fn postpone_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+    UPDATE issue_delivery_queue
+    SET
+    execute_after = now() + make_interval(secs => $3)
+    WHERE
+    newsletter_issue_id = $1 AND
+    subscriber_email = $2
+    "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        delay.as_secs_f64()
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn dead_letter_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i32,
+    error: &(dyn std::fmt::Debug + Sync),
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+    INSERT INTO failed_deliveries (
+        newsletter_issue_id,
+        subscriber_email,
+        n_attempts,
+        last_error,
+        failed_at
+    )
+    VALUES ($1, $2, $3, $4, now())
+    ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+    SET
+    n_attempts = EXCLUDED.n_attempts,
+    last_error = EXCLUDED.last_error,
+    failed_at = EXCLUDED.failed_at
+    "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        format!("{:?}", error)
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    DELETE FROM issue_delivery_queue
+    WHERE
+    newsletter_issue_id = $1 AND
+    subscriber_email = $2
+    "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+    UPDATE newsletter_issues
+    SET n_failed = n_failed + 1
+    WHERE newsletter_issue_id = $1
+    "#,
+        task.newsletter_issue_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::backoff_delay;
+    use crate::configuration::WorkerSettings;
+    use std::time::Duration;
+
+    fn settings() -> WorkerSettings {
+        WorkerSettings {
+            max_retries: 5,
+            min_backoff_milliseconds: 1000,
+            max_backoff_milliseconds: 60_000,
+            poll_interval_seconds: 10,
+            concurrency: 1,
+            messages_per_second: 10,
+            burst_size: 10,
+            batch_size: 10,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_every_retry() {
+        for n_retries in 0..5 {
+            let delay = backoff_delay(n_retries, &settings());
+            let expected = Duration::from_millis(1000 * 2u64.pow(n_retries as u32));
+            assert!(delay <= expected);
+            assert!(delay >= expected / 2);
+        }
+    }
+
+    #[test]
+    fn backoff_never_exceeds_the_maximum() {
+        for n_retries in [10, 31, 1000, i32::MAX] {
+            let delay = backoff_delay(n_retries, &settings());
+            assert!(delay <= settings().max_backoff());
+            assert!(delay >= settings().max_backoff() / 2);
+        }
+    }
+}
@@ -1,11 +1,17 @@
 pub mod authentication;
+pub mod client_ip;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod login_throttle;
+pub mod purge_worker;
+pub mod rate_limiter;
+pub mod request_limiter;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
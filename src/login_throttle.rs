@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+
+use crate::configuration::LoginThrottleSettings;
+use crate::request_limiter::count_in_window;
+
+/// Counts failed logins in Redis, per username and per IP address: wrong
+/// passwords and wrong second factor codes alike.
+///
+/// Past `free_failures` failed attempts a subject has to wait before trying
+/// again, twice as long after every new failure; once it reaches its maximum
+/// it is locked out for `lockout_seconds`. Failures are forgotten
+/// `window_seconds` after the first one.
+///
+/// Usernames are counted whether they exist or not, so being throttled says
+/// nothing about which accounts exist.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    redis: ConnectionManager,
+    settings: LoginThrottleSettings,
+}
+
+/// What a subject has to go through after a failed login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Penalty {
+    Delay(Duration),
+    Lockout(Duration),
+}
+
+impl Penalty {
+    pub fn duration(self) -> Duration {
+        match self {
+            Self::Delay(d) | Self::Lockout(d) => d,
+        }
+    }
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &str,
+        settings: LoginThrottleSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let redis = redis::Client::open(redis_uri)?
+            .get_connection_manager()
+            .await?;
+        Ok(Self { redis, settings })
+    }
+
+    /// How long until `username` or `ip` are allowed to try again, if they
+    /// are not right now.
+    #[tracing::instrument(skip(self))]
+    pub async fn blocked_for(
+        &self,
+        username: &str,
+        ip: Option<&str>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut pipe = redis::pipe();
+        for (scope, subject) in subjects(username, ip) {
+            pipe.pttl(self.key(scope, subject, "blocked"));
+        }
+        // -2 for a missing key, -1 for a key without expiry: neither blocks
+        let ttls: Vec<i64> = pipe.query_async(&mut self.redis.clone()).await?;
+        Ok(ttls
+            .into_iter()
+            .filter_map(|ttl| u64::try_from(ttl).ok())
+            .max()
+            .filter(|ttl| *ttl > 0)
+            .map(Duration::from_millis))
+    }
+
+    /// Count a failed login for `username` and `ip`, blocking them for a while
+    /// if they failed too often.
+    #[tracing::instrument(skip(self))]
+    pub async fn record_failure(
+        &self,
+        username: &str,
+        ip: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        for (scope, subject) in subjects(username, ip) {
+            let max_failures = match scope {
+                "ip" => self.settings.max_failures_per_ip,
+                _ => self.settings.max_failures_per_username,
+            };
+            let failures_key = self.key(scope, subject, "failures");
+            let (n_failures, _) =
+                count_in_window(&self.redis, &failures_key, self.settings.window()).await?;
+            let Some(penalty) = self.settings.penalty(n_failures, max_failures) else {
+                continue;
+            };
+            if let Penalty::Lockout(duration) = penalty {
+                tracing::warn!(
+                    scope,
+                    subject,
+                    n_failures,
+                    lockout_seconds = duration.as_secs(),
+                    "Locking out a login subject after too many failed attempts"
+                );
+            }
+            redis::cmd("SET")
+                .arg(self.key(scope, subject, "blocked"))
+                .arg(1)
+                .arg("PX")
+                .arg(penalty.duration().as_millis().max(1) as u64)
+                .query_async::<_, ()>(&mut self.redis.clone())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Forget the failed logins of `username`, once they are logged in, second
+    /// factor included. Their IP address keeps its count: logging into an account of
+    /// your own should not buy more guesses at somebody else's.
+    #[tracing::instrument(skip(self))]
+    pub async fn forget(&self, username: &str) -> Result<(), anyhow::Error> {
+        redis::cmd("DEL")
+            .arg(self.key("username", username, "failures"))
+            .arg(self.key("username", username, "blocked"))
+            .query_async::<_, ()>(&mut self.redis.clone())
+            .await?;
+        Ok(())
+    }
+
+    fn key(&self, scope: &str, subject: &str, kind: &str) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.settings.key_prefix, scope, subject, kind
+        )
+    }
+}
+
+fn subjects<'a>(username: &'a str, ip: Option<&'a str>) -> Vec<(&'static str, &'a str)> {
+    let mut subjects = vec![("username", username)];
+    if let Some(ip) = ip {
+        subjects.push(("ip", ip));
+    }
+    subjects
+}
+
+impl LoginThrottleSettings {
+    /// What comes after the `n_failures`-th failed login of a subject allowed
+    /// `max_failures` of them.
+    pub fn penalty(&self, n_failures: u64, max_failures: u64) -> Option<Penalty> {
+        if n_failures >= max_failures {
+            return Some(Penalty::Lockout(self.lockout()));
+        }
+        let n_delays = n_failures.checked_sub(self.free_failures)?.checked_sub(1)?;
+        let delay = self
+            .min_delay()
+            .saturating_mul(2u32.saturating_pow(n_delays.min(32) as u32))
+            .min(self.max_delay());
+        Some(Penalty::Delay(delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Penalty;
+    use crate::configuration::LoginThrottleSettings;
+    use claims::assert_none;
+    use std::time::Duration;
+
+    fn settings() -> LoginThrottleSettings {
+        LoginThrottleSettings {
+            free_failures: 3,
+            min_delay_milliseconds: 1000,
+            max_delay_milliseconds: 5000,
+            max_failures_per_username: 10,
+            max_failures_per_ip: 50,
+            lockout_seconds: 900,
+            window_seconds: 3600,
+            key_prefix: "login_throttle".into(),
+        }
+    }
+
+    #[test]
+    fn the_first_failures_are_free() {
+        let settings = settings();
+        for n_failures in 0..=3 {
+            assert_none!(settings.penalty(n_failures, 10));
+        }
+    }
+
+    #[test]
+    fn delays_double_after_every_failure_up_to_the_maximum() {
+        let settings = settings();
+        let delays: Vec<_> = (4..=9)
+            .map(|n_failures| settings.penalty(n_failures, 10))
+            .collect();
+        let expected: Vec<_> = [1, 2, 4, 5, 5, 5]
+            .into_iter()
+            .map(|s| Some(Penalty::Delay(Duration::from_secs(s))))
+            .collect();
+        assert_eq!(delays, expected);
+    }
+
+    #[test]
+    fn reaching_the_maximum_locks_the_subject_out() {
+        let settings = settings();
+        let lockout = Some(Penalty::Lockout(Duration::from_secs(900)));
+        assert_eq!(settings.penalty(10, 10), lockout);
+        assert_eq!(settings.penalty(11, 10), lockout);
+        // Even before any delay kicked in
+        assert_eq!(settings.penalty(2, 2), lockout);
+    }
+
+    #[test]
+    fn huge_failure_counts_do_not_overflow() {
+        let settings = settings();
+        assert_eq!(
+            settings.penalty(u64::MAX - 1, u64::MAX),
+            Some(Penalty::Delay(Duration::from_secs(5)))
+        );
+    }
+}
@@ -3,6 +3,8 @@ use std::fmt::{Debug, Display};
 use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::purge_worker::run_purge_worker_until_stopped;
+use zero2prod::shutdown::shutdown_signal;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -11,13 +13,33 @@ async fn main() -> anyhow::Result<()> {
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
     let configuration = get_configuration().expect("Failed to read configuration.");
+    let shutdown = shutdown_signal();
     let application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
-    tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
-    };
+    let application_task = tokio::spawn(application.run_until_stopped(shutdown.clone()));
+    let worker_task = tokio::spawn(run_worker_until_stopped(
+        configuration.clone(),
+        shutdown.clone(),
+    ));
+    let purge_task = tokio::spawn(run_purge_worker_until_stopped(
+        configuration,
+        shutdown.clone(),
+    ));
+    // Whichever task exits first takes the others down with it,
+    // but we still wait for all of them to wind down gracefully.
+    tokio::join!(
+        async {
+            report_exit("API", application_task.await);
+            shutdown.cancel();
+        },
+        async {
+            report_exit("Background worker", worker_task.await);
+            shutdown.cancel();
+        },
+        async {
+            report_exit("Purge worker", purge_task.await);
+            shutdown.cancel();
+        },
+    );
     Ok(())
 }
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::Settings;
+use crate::startup::get_connection_pool;
+
+/// Purges unconfirmed subscribers every `purge_interval` until `shutdown` is cancelled.
+pub async fn run_purge_worker_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    let settings = configuration.subscriptions;
+    loop {
+        if let Err(e) = purge_unconfirmed_subscribers(&pool, settings.unconfirmed_retention()).await
+        {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to purge unconfirmed subscribers",
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(settings.purge_interval()) => {}
+            _ = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
+
+/// Delete the people who subscribed but did not ask for a confirmation link
+/// in the last `retention`, returning how many there were.
+#[tracing::instrument(skip(pool))]
+pub async fn purge_unconfirmed_subscribers(
+    pool: &PgPool,
+    retention: Duration,
+) -> Result<u64, anyhow::Error> {
+    // Foreign keys are checked once the whole statement is done: the tokens are
+    // gone by then.
+    let result = sqlx::query!(
+        r#"
+        WITH stale AS (
+            SELECT id FROM subscriptions s
+            WHERE s.status = 'pending_confirmation' AND NOT EXISTS (
+                SELECT 1 FROM subscription_tokens t
+                WHERE t.subscriber_id = s.id
+                    AND t.created_at > now() - make_interval(secs => $1)
+            )
+            FOR UPDATE
+        ),
+        deleted_subscription_tokens AS (
+            DELETE FROM subscription_tokens
+            WHERE subscriber_id IN (SELECT id FROM stale)
+        ),
+        deleted_unsubscribe_tokens AS (
+            DELETE FROM unsubscribe_tokens
+            WHERE subscriber_id IN (SELECT id FROM stale)
+        )
+        DELETE FROM subscriptions
+        WHERE id IN (SELECT id FROM stale)
+        "#,
+        retention.as_secs_f64(),
+    )
+    .execute(pool)
+    .await?;
+    let n_purged = result.rows_affected();
+    if n_purged > 0 {
+        tracing::info!(n_purged, "Purged unconfirmed subscribers");
+    }
+    Ok(n_purged)
+}
@@ -0,0 +1,192 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A token bucket shared by every task sending emails on behalf of this process.
+///
+/// The bucket holds up to `burst_size` tokens and refills at `per_second` tokens
+/// per second; every call to `until_ready` takes one token, waiting for it if
+/// the bucket is empty. `acquire` takes several at once.
+pub struct RateLimiter {
+    emission_interval: Duration,
+    burst_tolerance: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// When the bucket will be full again, given the tokens handed out so far.
+    full_at: Instant,
+    /// Set when the email API asked us to back off.
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32, burst_size: u32) -> Self {
+        let emission_interval = Duration::from_secs(1) / per_second.max(1);
+        Self {
+            emission_interval,
+            burst_tolerance: emission_interval * burst_size.max(1).saturating_sub(1),
+            state: Mutex::new(State {
+                full_at: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait until we are allowed to send one more email.
+    pub async fn until_ready(&self) {
+        let ready_at = self.reserve();
+        tokio::time::sleep_until(ready_at).await;
+        // The email API might have asked us to slow down while we were waiting
+        while let Some(paused_until) = self.paused_until() {
+            tokio::time::sleep_until(paused_until).await;
+        }
+    }
+
+    /// Wait until at least one token is available, then take as many of the
+    /// available ones as we can, up to `max`. Hand back the ones you end up not
+    /// using with `release`.
+    pub async fn acquire(&self, max: u32) -> u32 {
+        loop {
+            if let Some(paused_until) = self.paused_until() {
+                tokio::time::sleep_until(paused_until).await;
+                continue;
+            }
+            match self.try_take(max.max(1)) {
+                Ok(n_tokens) => return n_tokens,
+                Err(ready_at) => tokio::time::sleep_until(ready_at).await,
+            }
+        }
+    }
+
+    /// Put back tokens taken with `acquire` that were not used.
+    pub fn release(&self, n_tokens: u32) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.full_at = state
+            .full_at
+            .checked_sub(self.emission_interval * n_tokens)
+            .unwrap_or(now)
+            .max(now);
+    }
+
+    /// Stop handing out tokens for `delay`, e.g. because the email API
+    /// answered with 429 Too Many Requests.
+    pub fn pause_for(&self, delay: Duration) {
+        let resume_at = Instant::now() + delay;
+        let mut state = self.state.lock().unwrap();
+        if state.paused_until.is_none_or(|t| t < resume_at) {
+            state.paused_until = Some(resume_at);
+        }
+    }
+
+    fn reserve(&self) -> Instant {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let full_at = state.full_at.max(now);
+        let ready_at = full_at
+            .checked_sub(self.burst_tolerance)
+            .unwrap_or(now)
+            .max(now);
+        state.full_at = full_at + self.emission_interval;
+        ready_at
+    }
+
+    /// Take up to `max` tokens if at least one is available, or tell when the
+    /// next one will be.
+    fn try_take(&self, max: u32) -> Result<u32, Instant> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let full_at = state.full_at.max(now);
+        let ready_at = full_at
+            .checked_sub(self.burst_tolerance)
+            .unwrap_or(now)
+            .max(now);
+        if ready_at > now {
+            return Err(ready_at);
+        }
+        let spare = self.burst_tolerance - (full_at - now);
+        let n_available = spare.as_nanos() / self.emission_interval.as_nanos() + 1;
+        let n_tokens = n_available.min(u128::from(max)) as u32;
+        state.full_at = full_at + self.emission_interval * n_tokens;
+        Ok(n_tokens)
+    }
+
+    fn paused_until(&self) -> Option<Instant> {
+        let mut state = self.state.lock().unwrap();
+        match state.paused_until {
+            Some(t) if t > Instant::now() => Some(t),
+            _ => {
+                state.paused_until = None;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_bucket_allows_a_burst() {
+        let limiter = RateLimiter::new(1, 5);
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.until_ready().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_empty_bucket_refills_at_the_configured_rate() {
+        let limiter = RateLimiter::new(10, 2);
+        let start = Instant::now();
+        for _ in 0..12 {
+            limiter.until_ready().await;
+        }
+        // 2 tokens straight away, then one every 100ms
+        assert_eq!(start.elapsed(), Duration::from_millis(1000));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_takes_every_available_token_up_to_the_maximum() {
+        let limiter = RateLimiter::new(10, 5);
+        let start = Instant::now();
+        assert_eq!(limiter.acquire(3).await, 3);
+        assert_eq!(limiter.acquire(10).await, 2);
+        // The bucket is empty: wait for the next token
+        assert_eq!(limiter.acquire(10).await, 1);
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn released_tokens_can_be_taken_again() {
+        let limiter = RateLimiter::new(1, 5);
+        let start = Instant::now();
+        assert_eq!(limiter.acquire(5).await, 5);
+        limiter.release(3);
+        assert_eq!(limiter.acquire(5).await, 3);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_waits_for_a_pause_to_end() {
+        let limiter = RateLimiter::new(100, 100);
+        let start = Instant::now();
+        limiter.pause_for(Duration::from_secs(3));
+        assert_eq!(limiter.acquire(10).await, 10);
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pausing_holds_back_every_caller() {
+        let limiter = RateLimiter::new(100, 100);
+        let start = Instant::now();
+        limiter.pause_for(Duration::from_secs(3));
+        limiter.until_ready().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+}
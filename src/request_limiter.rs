@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+
+use crate::configuration::RateLimitSettings;
+
+/// Counts requests in Redis, so every instance of the application shares the
+/// same budget.
+///
+/// Each subject (an IP address, an email address...) gets a fixed window of
+/// `window_seconds` that starts with its first request; requests past the
+/// maximum are refused until the window is over.
+#[derive(Clone)]
+pub struct RequestLimiter {
+    redis: ConnectionManager,
+    settings: RateLimitSettings,
+}
+
+impl RequestLimiter {
+    pub async fn new(redis_uri: &str, settings: RateLimitSettings) -> Result<Self, anyhow::Error> {
+        let redis = redis::Client::open(redis_uri)?
+            .get_connection_manager()
+            .await?;
+        Ok(Self { redis, settings })
+    }
+
+    pub fn settings(&self) -> &RateLimitSettings {
+        &self.settings
+    }
+
+    /// Record a request from `subject`, returning how long it has to wait if it
+    /// went over `max_requests`.
+    #[tracing::instrument(skip(self))]
+    pub async fn hit(
+        &self,
+        scope: &str,
+        subject: &str,
+        max_requests: u64,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let key = format!("{}:{}:{}", self.settings.key_prefix, scope, subject);
+        let (n_requests, retry_after) =
+            count_in_window(&self.redis, &key, self.settings.window()).await?;
+        if n_requests <= max_requests {
+            return Ok(None);
+        }
+        Ok(Some(retry_after))
+    }
+}
+
+/// Count one more event under `key`, in a fixed window of `window` that starts
+/// with the first event. Returns the count so far and how long until the
+/// window is over.
+pub async fn count_in_window(
+    redis: &ConnectionManager,
+    key: &str,
+    window: Duration,
+) -> Result<(u64, Duration), anyhow::Error> {
+    // Creating the key with its expiry and incrementing it in one
+    // transaction: a counter can never outlive its window.
+    let (n_events, ttl_milliseconds): (u64, i64) = redis::pipe()
+        .atomic()
+        .cmd("SET")
+        .arg(key)
+        .arg(0)
+        .arg("PX")
+        .arg(window.as_millis() as u64)
+        .arg("NX")
+        .ignore()
+        .incr(key, 1)
+        .pttl(key)
+        .query_async(&mut redis.clone())
+        .await?;
+    let remaining = u64::try_from(ttl_milliseconds)
+        .map(Duration::from_millis)
+        .unwrap_or(window);
+    Ok((n_events, remaining))
+}
@@ -1,3 +1,4 @@
+use crate::authentication::Role;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::{http::header::ContentType, web, HttpResponse};
@@ -7,6 +8,7 @@ use uuid::Uuid;
 
 pub async fn admin_dashboard(
     session: TypedSession,
+    role: web::ReqData<Role>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let username = if let Some(user_id) = session.get_user_id().map_err(e500)? {
@@ -14,6 +16,12 @@ pub async fn admin_dashboard(
     } else {
         return Ok(see_other("/login"));
     };
+    let role = role.into_inner();
+    let users_html = if role.includes(Role::Owner) {
+        r#"<li><a href="/admin/users">Users</a></li>"#
+    } else {
+        ""
+    };
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -25,10 +33,15 @@ pub async fn admin_dashboard(
             </head>
             <body>
             <p>Welcome {username}!</p>
+            <p>Role: {role}</p>
             <p>Available actions:</p>
             <ol>
             <li><a href="/admin/password">Change password</a></li>
+            <li><a href="/admin/two_factor">Two-factor authentication</a></li>
             <li><a href="/admin/newsletters">Send News Letter</a></li>
+            <li><a href="/admin/deliveries/failed">Failed deliveries</a></li>
+            <li><a href="/admin/subscribers">Subscribers</a></li>
+            {users_html}
             <li>
             <form name="logoutForm" action="/admin/logout" method="post">
                 <input type="submit" value="Logout">
@@ -37,7 +50,8 @@ pub async fn admin_dashboard(
             </ol>
             </body>
             </html>
-            "#
+            "#,
+            role = role.as_str(),
         )))
 }
 
@@ -0,0 +1,123 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+struct FailedDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i32,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn failed_deliveries(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let deliveries = get_failed_deliveries(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for d in &deliveries {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{email}</td>
+                <td>{n_attempts}</td>
+                <td>{failed_at}</td>
+                <td><pre>{error}</pre></td>
+                <td>
+                    <form action="/admin/deliveries/failed/requeue" method="post">
+                        <input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+                        <input hidden type="text" name="subscriber_email" value="{email}">
+                        <button type="submit">Re-enqueue</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = encode_minimal(&d.title),
+            email = encode_minimal(&d.subscriber_email),
+            n_attempts = d.n_attempts,
+            failed_at = d.failed_at.to_rfc3339(),
+            error = encode_minimal(&d.last_error),
+            issue_id = d.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    let content_html = if deliveries.is_empty() {
+        "<p>There are no failed deliveries.</p>".to_string()
+    } else {
+        format!(
+            r#"<form action="/admin/deliveries/failed/requeue_all" method="post">
+                <button type="submit">Re-enqueue all</button>
+            </form>
+            <table>
+                <tr>
+                    <th>Issue</th>
+                    <th>Subscriber</th>
+                    <th>Attempts</th>
+                    <th>Failed at</th>
+                    <th>Error</th>
+                    <th></th>
+                </tr>
+                {rows_html}
+            </table>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Failed deliveries</title>
+            </head>
+            <body>
+                {msg_html}
+                {content_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get failed deliveries", skip(pool))]
+async fn get_failed_deliveries(pool: &PgPool) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.n_attempts,
+            f.last_error,
+            f.failed_at
+        FROM failed_deliveries f
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        ORDER BY f.failed_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve failed deliveries.")?;
+    Ok(rows)
+}
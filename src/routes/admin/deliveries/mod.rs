@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::failed_deliveries;
+pub use post::{requeue_all_failed_deliveries, requeue_failed_delivery};
@@ -0,0 +1,120 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::issue_delivery_worker::notify_workers;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(
+    name = "Re-enqueue a failed delivery",
+    skip_all,
+    fields(
+        newsletter_issue_id=%form.newsletter_issue_id,
+        subscriber_email=%form.subscriber_email
+    )
+)]
+pub async fn requeue_failed_delivery(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let delivery = (form.newsletter_issue_id, form.subscriber_email.as_str());
+    let n_requeued = requeue_failed_deliveries(&pool, Some(delivery))
+        .await
+        .context("Failed to re-enqueue a failed delivery")
+        .map_err(e500)?;
+    if n_requeued == 0 {
+        FlashMessage::error(
+            "The delivery is no longer marked as failed, is already queued, its issue \
+            was cancelled or the subscriber is no longer confirmed.",
+        )
+        .send();
+    } else {
+        FlashMessage::info("The delivery has been re-enqueued.").send();
+    }
+    Ok(see_other("/admin/deliveries/failed"))
+}
+
+#[tracing::instrument(name = "Re-enqueue all failed deliveries", skip_all)]
+pub async fn requeue_all_failed_deliveries(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_requeued = requeue_failed_deliveries(&pool, None)
+        .await
+        .context("Failed to re-enqueue failed deliveries")
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} deliveries have been re-enqueued.", n_requeued)).send();
+    Ok(see_other("/admin/deliveries/failed"))
+}
+
+/// Move failed deliveries back to the queue: all of them, or only `delivery`.
+/// Returns how many were re-enqueued.
+async fn requeue_failed_deliveries(
+    pool: &PgPool,
+    delivery: Option<(Uuid, &str)>,
+) -> Result<u64, anyhow::Error> {
+    let (newsletter_issue_id, subscriber_email) = delivery.unzip();
+    let n_requeued = sqlx::query_scalar!(
+        r#"
+        WITH candidates AS (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM failed_deliveries
+            WHERE
+                ($1::uuid IS NULL OR newsletter_issue_id = $1)
+                AND ($2::text IS NULL OR subscriber_email = $2)
+                -- Nothing goes out for cancelled issues anymore
+                AND newsletter_issue_id IN (
+                    SELECT newsletter_issue_id FROM newsletter_issues
+                    WHERE status <> 'cancelled'
+                )
+                -- ...nor to people who unsubscribed since
+                AND subscriber_email IN (
+                    SELECT email FROM subscriptions WHERE status = 'confirmed'
+                )
+            FOR UPDATE
+        ),
+        enqueued AS (
+            INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+            SELECT newsletter_issue_id, subscriber_email FROM candidates
+            ON CONFLICT DO NOTHING
+            RETURNING newsletter_issue_id, subscriber_email
+        ),
+        -- Only what actually made it back to the queue stops being failed
+        requeued AS (
+            DELETE FROM failed_deliveries f
+            USING enqueued e
+            WHERE
+                f.newsletter_issue_id = e.newsletter_issue_id
+                AND f.subscriber_email = e.subscriber_email
+            RETURNING f.newsletter_issue_id
+        ),
+        -- Re-enqueued deliveries are pending again
+        progress AS (
+            UPDATE newsletter_issues i
+            SET n_failed = n_failed - r.n_requeued
+            FROM (
+                SELECT newsletter_issue_id, count(*) AS n_requeued
+                FROM requeued
+                GROUP BY newsletter_issue_id
+            ) r
+            WHERE i.newsletter_issue_id = r.newsletter_issue_id
+        )
+        SELECT count(*) AS "n_requeued!" FROM requeued
+        "#,
+        newsletter_issue_id,
+        subscriber_email,
+    )
+    .fetch_one(pool)
+    .await?;
+    notify_workers(pool)
+        .await
+        .context("Failed to notify the delivery workers")?;
+    Ok(n_requeued as u64)
+}
@@ -0,0 +1,134 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use futures_util::{Stream, TryStreamExt};
+use tokio::sync::mpsc;
+
+/// How many encoded rows can wait for a slow client before we stop reading
+/// from the database.
+const CHANNEL_CAPACITY: usize = 64;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+type Chunk = Result<Bytes, anyhow::Error>;
+
+/// The sending half of an export: whatever goes in comes out of the response
+/// body, one row at a time.
+pub struct ExportSender {
+    format: ExportFormat,
+    sender: mpsc::Sender<Chunk>,
+}
+
+/// Start a download of `{name}.csv` or `{name}.json`. The response body is
+/// whatever `ExportSender::send_rows` is given, from another task.
+pub fn export_response(format: ExportFormat, name: &str) -> (ExportSender, HttpResponse) {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    let response = HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}.{}",
+                name,
+                format.extension()
+            ))],
+        })
+        .streaming(body);
+    (ExportSender { format, sender }, response)
+}
+
+impl ExportSender {
+    /// Encode and send every row of `rows`. Stops early if the client went away;
+    /// a failing query aborts the download rather than leaving it looking complete.
+    pub async fn send_rows<T, S>(self, rows: S)
+    where
+        T: serde::Serialize,
+        S: Stream<Item = Result<T, sqlx::Error>>,
+    {
+        if let Err(e) = self.try_send_rows(rows).await {
+            if self.sender.is_closed() {
+                tracing::info!("The client stopped downloading the export");
+                return;
+            }
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to export rows"
+            );
+            let _ = self.sender.send(Err(e)).await;
+        }
+    }
+
+    async fn try_send_rows<T, S>(&self, rows: S) -> Result<(), anyhow::Error>
+    where
+        T: serde::Serialize,
+        S: Stream<Item = Result<T, sqlx::Error>>,
+    {
+        futures_util::pin_mut!(rows);
+        let mut first_row = true;
+        if let ExportFormat::Json = self.format {
+            self.send(b"[".to_vec()).await?;
+        }
+        while let Some(row) = rows.try_next().await? {
+            let chunk = match self.format {
+                ExportFormat::Csv => {
+                    // The header goes out along with the first row
+                    let mut writer = csv::WriterBuilder::new()
+                        .has_headers(first_row)
+                        .from_writer(vec![]);
+                    writer.serialize(&row)?;
+                    writer.into_inner().map_err(|e| e.into_error())?
+                }
+                ExportFormat::Json => {
+                    let mut chunk = if first_row { vec![] } else { b",".to_vec() };
+                    serde_json::to_writer(&mut chunk, &row)?;
+                    chunk
+                }
+            };
+            first_row = false;
+            self.send(chunk).await?;
+        }
+        if let ExportFormat::Json = self.format {
+            self.send(b"]".to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    async fn send(&self, chunk: Vec<u8>) -> Result<(), anyhow::Error> {
+        self.sender
+            .send(Ok(Bytes::from(chunk)))
+            .await
+            .map_err(|_| anyhow::anyhow!("The client stopped downloading the export"))
+    }
+}
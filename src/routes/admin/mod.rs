@@ -1,8 +1,17 @@
 mod dashboard;
-pub use dashboard::admin_dashboard;
+pub use dashboard::{admin_dashboard, get_username};
+mod deliveries;
+pub use deliveries::*;
+mod export;
 mod password;
 pub use password::*;
 mod logout;
 pub use logout::log_out;
 mod newsletters;
 pub use newsletters::*;
+mod subscribers;
+pub use subscribers::*;
+mod two_factor;
+pub use two_factor::*;
+mod users;
+pub use users::*;
@@ -0,0 +1,217 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::notify_workers,
+    utils::{e400, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct IssueActionForm {
+    idempotency_key: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum IssueAction {
+    Pause,
+    Resume,
+    Cancel,
+}
+
+impl IssueAction {
+    /// The statuses an issue can be in for the action to apply.
+    fn allowed_from(&self) -> &'static [&'static str] {
+        match self {
+            IssueAction::Pause => &["published"],
+            IssueAction::Resume => &["paused"],
+            IssueAction::Cancel => &["scheduled", "published", "paused"],
+        }
+    }
+
+    fn new_status(&self) -> &'static str {
+        match self {
+            IssueAction::Pause => "paused",
+            IssueAction::Resume => "published",
+            IssueAction::Cancel => "cancelled",
+        }
+    }
+
+    fn success_message(&self) -> FlashMessage {
+        FlashMessage::info(match self {
+            IssueAction::Pause => "The delivery of the issue has been paused.",
+            IssueAction::Resume => "The delivery of the issue has been resumed.",
+            IssueAction::Cancel => "The issue has been cancelled.",
+        })
+    }
+
+    fn failure_message(&self) -> FlashMessage {
+        FlashMessage::error(match self {
+            IssueAction::Pause => "Only issues that are being delivered can be paused.",
+            IssueAction::Resume => "Only paused issues can be resumed.",
+            IssueAction::Cancel => "The issue has already been cancelled.",
+        })
+    }
+}
+
+pub async fn pause_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<IssueActionForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    apply_issue_action(IssueAction::Pause, newsletter_issue_id, form, pool, user_id).await
+}
+
+pub async fn resume_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<IssueActionForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    apply_issue_action(
+        IssueAction::Resume,
+        newsletter_issue_id,
+        form,
+        pool,
+        user_id,
+    )
+    .await
+}
+
+pub async fn cancel_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<IssueActionForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    apply_issue_action(
+        IssueAction::Cancel,
+        newsletter_issue_id,
+        form,
+        pool,
+        user_id,
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Change the delivery status of a newsletter issue",
+    skip(newsletter_issue_id, form, pool, user_id),
+    fields(newsletter_issue_id=%*newsletter_issue_id, user_id=%&*user_id)
+)]
+async fn apply_issue_action(
+    action: IssueAction,
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<IssueActionForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let idempotency_key: IdempotencyKey = form.0.idempotency_key.try_into().map_err(e400)?;
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            action.success_message().send();
+            return Ok(saved_response);
+        }
+    };
+    if let IssueAction::Cancel = action {
+        // Workers lock a delivery before they update its issue: we must take our
+        // locks in the same order, or cancelling in the middle of a send deadlocks.
+        cancel_pending_deliveries(&mut transaction, newsletter_issue_id)
+            .await
+            .context("Failed to cancel pending deliveries")
+            .map_err(e500)?;
+    }
+    let applied = change_issue_status(&mut transaction, newsletter_issue_id, action)
+        .await
+        .context("Failed to change the status of a newsletter issue")
+        .map_err(e500)?;
+    if applied {
+        match action {
+            IssueAction::Cancel => {
+                // A scheduled issue might have been enqueued while we were waiting
+                cancel_pending_deliveries(&mut transaction, newsletter_issue_id)
+                    .await
+                    .context("Failed to cancel pending deliveries")
+                    .map_err(e500)?;
+            }
+            IssueAction::Resume => {
+                notify_workers(&mut *transaction)
+                    .await
+                    .context("Failed to notify the delivery workers")
+                    .map_err(e500)?;
+            }
+            IssueAction::Pause => {}
+        }
+    }
+    let response = see_other(&format!("/admin/newsletters/{}", newsletter_issue_id));
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+    if applied {
+        action.success_message().send();
+    } else {
+        action.failure_message().send();
+    }
+    Ok(response)
+}
+
+/// Returns `false` if the issue is not in a status the action applies to.
+#[tracing::instrument(skip(transaction))]
+async fn change_issue_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    action: IssueAction,
+) -> Result<bool, sqlx::Error> {
+    let allowed_from: Vec<String> = action
+        .allowed_from()
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let query = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $2
+        WHERE newsletter_issue_id = $1 AND status = ANY($3)
+        "#,
+        newsletter_issue_id,
+        action.new_status(),
+        &allowed_from,
+    );
+    let n_updated = transaction.execute(query).await?.rows_affected();
+    Ok(n_updated > 0)
+}
+
+/// Deliveries being sent right now hold a lock on their row: we wait for them
+/// to go out before removing whatever is left.
+#[tracing::instrument(skip(transaction))]
+async fn cancel_pending_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        WITH cancelled AS (
+            DELETE FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1
+            RETURNING subscriber_email
+        )
+        UPDATE newsletter_issues
+        SET n_cancelled = n_cancelled + (SELECT count(*) FROM cancelled)
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::admin::export::{export_response, ExportQuery};
+use crate::utils::{e404, e500};
+
+#[derive(serde::Serialize)]
+struct ExportedDelivery {
+    subscriber_email: String,
+    /// `sent`, `failed` or `pending`.
+    outcome: String,
+    at: DateTime<Utc>,
+    error: Option<String>,
+}
+
+#[tracing::instrument(name = "Export the recipients of a newsletter issue", skip(pool))]
+pub async fn export_newsletter_issue_deliveries(
+    newsletter_issue_id: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issue")
+    .map_err(e500)?
+    .ok_or_else(|| e404("There is no such newsletter issue."))?;
+
+    let (sender, response) = export_response(
+        query.format,
+        &format!("newsletter-{}-deliveries", newsletter_issue_id),
+    );
+    let pool = pool.get_ref().clone();
+    tokio::spawn(async move {
+        let rows = sqlx::query_as!(
+            ExportedDelivery,
+            r#"
+            SELECT
+                subscriber_email AS "subscriber_email!",
+                outcome AS "outcome!",
+                at AS "at!",
+                error
+            FROM (
+                SELECT subscriber_email, 'sent' AS outcome, sent_at AS at, NULL AS error
+                FROM sent_deliveries WHERE newsletter_issue_id = $1
+                UNION ALL
+                SELECT subscriber_email, 'failed', failed_at, last_error
+                FROM failed_deliveries WHERE newsletter_issue_id = $1
+                UNION ALL
+                SELECT subscriber_email, 'pending', execute_after, NULL
+                FROM issue_delivery_queue WHERE newsletter_issue_id = $1
+            ) d
+            ORDER BY subscriber_email
+            "#,
+            newsletter_issue_id,
+        )
+        .fetch(&pool);
+        sender.send_rows(rows).await;
+    });
+    Ok(response)
+}
@@ -1,12 +1,55 @@
 use actix_web::{
     cookie::{time::Duration, Cookie},
     http::header::ContentType,
-    HttpResponse,
+    web, HttpResponse,
 };
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
 
-pub async fn newsletter_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+use crate::utils::{e404, e500};
+
+struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    published_at: DateTime<Utc>,
+}
+
+struct IssueProgress {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    published_at: DateTime<Utc>,
+    n_enqueued: i32,
+    n_sent: i32,
+    n_failed: i32,
+    n_cancelled: i32,
+    queued_at: Option<DateTime<Utc>>,
+    first_sent_at: Option<DateTime<Utc>>,
+    last_sent_at: Option<DateTime<Utc>>,
+}
+
+impl IssueProgress {
+    fn n_pending(&self) -> i32 {
+        self.n_enqueued - self.n_sent - self.n_failed - self.n_cancelled
+    }
+}
+
+fn format_timestamp(timestamp: Option<DateTime<Utc>>) -> String {
+    timestamp
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_else(|| "-".into())
+}
+
+pub async fn newsletter_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
     let mut error_html = String::new();
 
     for m in flash_messages.iter() {
@@ -14,7 +57,21 @@ pub async fn newsletter_form(flash_messages: IncomingFlashMessages) -> HttpRespo
     }
     let idempotency_key = uuid::Uuid::new_v4();
 
-    HttpResponse::Ok()
+    let issues = get_published_issues(&pool).await.map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in &issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/admin/newsletters/{issue_id}">{title}</a> ({status}, {published_at})</li>"#,
+            issue_id = issue.newsletter_issue_id,
+            title = encode_minimal(&issue.title),
+            status = issue.status,
+            published_at = issue.published_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
         .body(format!(
@@ -53,11 +110,161 @@ pub async fn newsletter_form(flash_messages: IncomingFlashMessages) -> HttpRespo
                             ></textarea>
                         </label>
                         <br>
+                        <label>Send at (UTC, leave empty to send straight away):
+                            <input
+                                type="datetime-local"
+                                name="send_at"
+                            >
+                        </label>
+                        <br>
                         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
                         <button type="submit">Post</button>
                     </form>
+                    <p>Issues:</p>
+                    <ul>
+                        {issues_html}
+                    </ul>
                     <p><a href="/admin/dashboard">&lt;- Back</a></p>
                 </body>
             </html>"#,
-        ))
+        )))
+}
+
+pub async fn newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let progress = get_issue_progress(&pool, *newsletter_issue_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("There is no such newsletter issue."))?;
+    let mut actions_html = String::new();
+    let issue_id = progress.newsletter_issue_id;
+    if progress.status == "scheduled" {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/newsletters/{issue_id}/reschedule" method="post">
+                <label>Send at (UTC):
+                    <input type="datetime-local" name="send_at">
+                </label>
+                <button type="submit">Reschedule</button>
+            </form>"#
+        )
+        .unwrap();
+    }
+    let actions: &[(&str, &str)] = match progress.status.as_str() {
+        "published" => &[("pause", "Pause"), ("cancel", "Cancel")],
+        "paused" => &[("resume", "Resume"), ("cancel", "Cancel")],
+        "scheduled" => &[("cancel", "Cancel")],
+        _ => &[],
+    };
+    for (action, label) in actions {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/newsletters/{issue_id}/{action}" method="post">
+                <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+                <button type="submit">{label}</button>
+            </form>"#,
+            idempotency_key = Uuid::new_v4(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Newsletter issue</title>
+            </head>
+            <body>
+                {msg_html}
+                <h1>{title}</h1>
+                <table>
+                    <tr><th>Status</th><td>{status}</td></tr>
+                    <tr><th>Send at</th><td>{published_at}</td></tr>
+                    <tr><th>Recipients enqueued</th><td>{n_enqueued}</td></tr>
+                    <tr><th>Sent</th><td>{n_sent}</td></tr>
+                    <tr><th>Failed</th><td><a href="/admin/deliveries/failed">{n_failed}</a></td></tr>
+                    <tr><th>Cancelled</th><td>{n_cancelled}</td></tr>
+                    <tr><th>Pending</th><td>{n_pending}</td></tr>
+                    <tr><th>Queued at</th><td>{queued_at}</td></tr>
+                    <tr><th>First send</th><td>{first_sent_at}</td></tr>
+                    <tr><th>Last send</th><td>{last_sent_at}</td></tr>
+                </table>
+                <p>
+                    Export the recipients as
+                    <a href="/admin/newsletters/{issue_id}/export?format=csv">CSV</a> or
+                    <a href="/admin/newsletters/{issue_id}/export?format=json">JSON</a>
+                </p>
+                {actions_html}
+                <p><a href="/admin/newsletters">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+            title = encode_minimal(&progress.title),
+            status = progress.status,
+            published_at = progress.published_at.to_rfc3339(),
+            n_enqueued = progress.n_enqueued,
+            n_sent = progress.n_sent,
+            n_failed = progress.n_failed,
+            n_cancelled = progress.n_cancelled,
+            n_pending = progress.n_pending(),
+            queued_at = format_timestamp(progress.queued_at),
+            first_sent_at = format_timestamp(progress.first_sent_at),
+            last_sent_at = format_timestamp(progress.last_sent_at),
+        )))
+}
+
+#[tracing::instrument(name = "Get published newsletter issues", skip(pool))]
+async fn get_published_issues(pool: &PgPool) -> Result<Vec<PublishedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, status, published_at
+        FROM newsletter_issues
+        ORDER BY published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve published newsletter issues.")?;
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get newsletter issue progress", skip(pool))]
+async fn get_issue_progress(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueProgress>, anyhow::Error> {
+    let progress = sqlx::query_as!(
+        IssueProgress,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            status,
+            published_at,
+            n_enqueued,
+            n_sent,
+            n_failed,
+            n_cancelled,
+            queued_at,
+            first_sent_at,
+            last_sent_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the progress of a newsletter issue.")?;
+    Ok(progress)
 }
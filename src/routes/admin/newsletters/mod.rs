@@ -1,3 +1,9 @@
+mod actions;
+mod export;
 pub mod get;
 pub mod post;
+mod schedule;
+pub use actions::{cancel_newsletter_issue, pause_newsletter_issue, resume_newsletter_issue};
+pub use export::export_newsletter_issue_deliveries;
 pub use post::*;
+pub use schedule::reschedule_newsletter_issue;
@@ -5,12 +5,14 @@ use actix_web::{
 };
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
     authentication::UserId,
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::enqueue_delivery_tasks,
     utils::{e400, e500, error_chain_fmt, see_other},
 };
 
@@ -20,6 +22,7 @@ pub struct NewsletterForm {
     text_content: String,
     html_content: String,
     idempotency_key: String,
+    send_at: Option<String>,
 }
 
 #[derive(thiserror::Error)]
@@ -73,8 +76,16 @@ pub async fn publish_newsletter(
         text_content,
         html_content,
         idempotency_key,
+        send_at,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let send_at = match parse_send_at(send_at.as_deref().unwrap_or_default()) {
+        Ok(send_at) => send_at,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
@@ -85,22 +96,56 @@ pub async fn publish_newsletter(
             return Ok(saved_response);
         }
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        send_at,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    // Scheduled issues are enqueued by the delivery worker once they are due
+    if send_at.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
     let response = see_other("/admin/newsletters");
     let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
         .map_err(e500)?;
-    success_message().send();
+    match send_at {
+        Some(send_at) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            send_at.to_rfc3339()
+        ))
+        .send(),
+        None => success_message().send(),
+    }
     Ok(response)
 }
 
+/// Parse the optional "send at" time of an issue. The newsletter form uses a
+/// `datetime-local` input, which carries no timezone: we treat it as UTC.
+pub fn parse_send_at(raw: &str) -> Result<Option<DateTime<Utc>>, String> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    let send_at = DateTime::parse_from_rfc3339(raw)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M").map(|t| t.and_utc()))
+        .or_else(|_| NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S").map(|t| t.and_utc()))
+        .map_err(|_| format!("{} is not a valid date and time.", raw))?;
+    if send_at <= Utc::now() {
+        return Err("The send time must be in the future.".into());
+    }
+    Ok(Some(send_at))
+}
+
 fn success_message() -> FlashMessage {
     FlashMessage::info(
         "The newsletter issue has been accepted - \
@@ -114,8 +159,14 @@ async fn insert_newsletter_issue(
     title: &str,
     text_content: &str,
     html_content: &str,
+    send_at: Option<DateTime<Utc>>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    let status = if send_at.is_some() {
+        "scheduled"
+    } else {
+        "published"
+    };
     let query = sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
@@ -123,36 +174,58 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            published_at,
+            status
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, COALESCE($5, now()), $6)
         "#,
         newsletter_issue_id,
         title,
         text_content,
-        html_content
+        html_content,
+        send_at,
+        status
     );
     transaction.execute(query).await?;
     Ok(newsletter_issue_id)
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    let query = sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id,
-    );
-    transaction.execute(query).await?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::parse_send_at;
+    use chrono::Utc;
+    use claims::{assert_err, assert_none, assert_some};
+    use std::time::Duration;
+
+    #[test]
+    fn an_empty_send_at_means_straight_away() {
+        assert_none!(parse_send_at("").unwrap());
+        assert_none!(parse_send_at("  ").unwrap());
+    }
+
+    #[test]
+    fn datetime_local_inputs_are_treated_as_utc() {
+        let send_at = (Utc::now() + Duration::from_secs(24 * 60 * 60)).naive_utc();
+        let raw = send_at.format("%Y-%m-%dT%H:%M").to_string();
+        let parsed = assert_some!(parse_send_at(&raw).unwrap());
+        assert_eq!(parsed.format("%Y-%m-%dT%H:%M").to_string(), raw);
+    }
+
+    #[test]
+    fn rfc3339_timestamps_are_accepted() {
+        let send_at = Utc::now() + Duration::from_secs(24 * 60 * 60);
+        let parsed = assert_some!(parse_send_at(&send_at.to_rfc3339()).unwrap());
+        assert_eq!(parsed, send_at);
+    }
+
+    #[test]
+    fn a_send_at_in_the_past_is_rejected() {
+        let send_at = Utc::now() - Duration::from_secs(60);
+        assert_err!(parse_send_at(&send_at.to_rfc3339()));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(parse_send_at("tomorrow-ish"));
+    }
 }
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::post::parse_send_at;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleForm {
+    send_at: String,
+}
+
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip_all,
+    fields(newsletter_issue_id=%newsletter_issue_id)
+)]
+pub async fn reschedule_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<RescheduleForm>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue_page = format!("/admin/newsletters/{}", newsletter_issue_id);
+    let send_at = match parse_send_at(&form.send_at) {
+        Ok(Some(send_at)) => send_at,
+        Ok(None) => {
+            FlashMessage::error("Pick the time the issue should be sent at.").send();
+            return Ok(see_other(&issue_page));
+        }
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&issue_page));
+        }
+    };
+    let n_rescheduled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = $2
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        newsletter_issue_id,
+        send_at,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to reschedule a newsletter issue")
+    .map_err(e500)?
+    .rows_affected();
+    if n_rescheduled == 0 {
+        FlashMessage::error("Only scheduled issues can be rescheduled.").send();
+    } else {
+        FlashMessage::info(format!(
+            "The issue has been rescheduled for {}.",
+            send_at.to_rfc3339()
+        ))
+        .send();
+    }
+    Ok(see_other(&issue_page))
+}
@@ -4,7 +4,10 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials, UserId},
+    authentication::{
+        check_password_strength, validate_credentials, AuthError, Credentials, PasswordHashing,
+        UserId,
+    },
     routes::admin::dashboard::get_username,
     utils::{e500, see_other},
 };
@@ -19,6 +22,7 @@ pub async fn change_password(
     form: web::Form<FormData>,
     user_id: web::ReqData<UserId>,
     pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
@@ -29,17 +33,8 @@ pub async fn change_password(
         return Ok(see_other("/admin/password"));
     }
 
-    let password_max_length = 129;
-    let password_min_length = 10;
-
-    if form.new_password.expose_secret().len() > password_max_length
-        || form.new_password.expose_secret().len() < password_min_length
-    {
-        FlashMessage::error(format!(
-            "Passwords need to be {}-{} characters long.",
-            password_min_length, password_max_length
-        ))
-        .send();
+    if let Err(e) = check_password_strength(&form.new_password) {
+        FlashMessage::error(e).send();
         return Ok(see_other("/admin/password"));
     }
 
@@ -49,7 +44,7 @@ pub async fn change_password(
         username,
         password: form.0.current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -58,7 +53,7 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    crate::authentication::change_password(*user_id, form.0.new_password, &hashing, &pool)
         .await
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
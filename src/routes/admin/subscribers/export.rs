@@ -0,0 +1,35 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::routes::admin::export::{export_response, ExportQuery};
+
+#[derive(serde::Serialize)]
+struct ExportedSubscriber {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Export subscribers", skip(pool))]
+pub async fn export_subscribers(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (sender, response) = export_response(query.format, "subscribers");
+    let pool = pool.get_ref().clone();
+    tokio::spawn(async move {
+        let rows = sqlx::query_as!(
+            ExportedSubscriber,
+            r#"
+            SELECT email, name, status, subscribed_at
+            FROM subscriptions
+            ORDER BY subscribed_at, id
+            "#,
+        )
+        .fetch(&pool);
+        sender.send_rows(rows).await;
+    });
+    response
+}
@@ -0,0 +1,320 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use htmlescape::encode_minimal;
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::post::delete_subscription;
+use crate::routes::cancel_pending_deliveries_to;
+use crate::startup::HmacSecret;
+use crate::utils::{e400, e500, see_other};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct DataSubject {
+    email: String,
+}
+
+impl DataSubject {
+    fn email(&self) -> Result<&str, actix_web::Error> {
+        match self.email.trim() {
+            "" => Err(e400("An email address is required.")),
+            email => Ok(email),
+        }
+    }
+}
+
+/// Everything we hold about an email address, whatever its case.
+#[derive(serde::Serialize)]
+struct SubjectData {
+    email: String,
+    /// Several when the address was stored with different cases.
+    subscriptions: Vec<Subscription>,
+    confirmation_tokens: Vec<ConfirmationToken>,
+    pending_deliveries: Vec<PendingDelivery>,
+    sent_deliveries: Vec<SentDelivery>,
+    failed_deliveries: Vec<FailedDelivery>,
+    /// When the address was erased, if it was.
+    suppressed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct Subscription {
+    #[serde(skip)]
+    id: Uuid,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    unsubscribe_token: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ConfirmationToken {
+    subscription_token: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct PendingDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    n_retries: i32,
+    execute_after: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct SentDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    sent_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct FailedDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    n_attempts: i32,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+/// Addresses are compared without their case: `Ursula@example.com` is erased
+/// along with `ursula@example.com`. The hash is keyed: email addresses are
+/// too easy to guess for a plain hash to hide them.
+fn suppression_hash(secret: &HmacSecret, email: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.0.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(email.trim().to_lowercase().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Whether the address was erased, and must not be imported again.
+pub(super) async fn is_suppressed<'c, E>(
+    executor: E,
+    secret: &HmacSecret,
+    email: &str,
+) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        "SELECT email_hash FROM suppressed_emails WHERE email_hash = $1",
+        suppression_hash(secret, email),
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "Export the data held about an email address", skip_all)]
+pub async fn subject_data(
+    query: web::Query<DataSubject>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = query.email()?;
+    let data = get_subject_data(&pool, &hmac_secret, email)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subject-data.json".into())],
+        })
+        .json(data))
+}
+
+#[tracing::instrument(name = "Erase an email address", skip_all)]
+pub async fn erase_subject(
+    form: web::Form<DataSubject>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email()?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let n_erased = erase(&mut transaction, &hmac_secret, email)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase an email address")
+        .map_err(e500)?;
+    // Flash messages are rendered as HTML, and the address is whatever was typed
+    let email = encode_minimal(email);
+    if n_erased == 0 {
+        FlashMessage::info(format!(
+            "Nothing was held about {}. The address will not be imported again.",
+            email
+        ))
+        .send();
+    } else {
+        FlashMessage::info(format!(
+            "Everything about {} has been erased. The address will not be imported again.",
+            email
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
+
+/// Delete every row linked to `email`, leaving only its suppression record.
+/// Returns how many subscriptions and deliveries were deleted.
+async fn erase(
+    transaction: &mut Transaction<'_, Postgres>,
+    secret: &HmacSecret,
+    email: &str,
+) -> Result<u64, anyhow::Error> {
+    let subscribers = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1) FOR UPDATE",
+        email
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to retrieve the subscriber")?;
+    // This waits for a worker that is sending to them right now, so its
+    // delivery log is there to be deleted below.
+    let mut n_erased = cancel_pending_deliveries_to(transaction, email)
+        .await
+        .context("Failed to cancel the deliveries still pending for the subscriber")?;
+    for subscriber in &subscribers {
+        delete_subscription(transaction, subscriber.id).await?;
+    }
+    n_erased += subscribers.len() as u64;
+    let query = sqlx::query!(
+        "DELETE FROM sent_deliveries WHERE lower(subscriber_email) = lower($1)",
+        email
+    );
+    n_erased += transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the sent deliveries")?
+        .rows_affected();
+    let query = sqlx::query!(
+        "DELETE FROM failed_deliveries WHERE lower(subscriber_email) = lower($1)",
+        email
+    );
+    n_erased += transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the failed deliveries")?
+        .rows_affected();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email_hash, suppressed_at)
+        VALUES ($1, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        suppression_hash(secret, email),
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to record the suppression of the address")?;
+    Ok(n_erased)
+}
+
+#[tracing::instrument(name = "Get the data held about an email address", skip_all)]
+async fn get_subject_data(
+    pool: &PgPool,
+    secret: &HmacSecret,
+    email: &str,
+) -> Result<SubjectData, anyhow::Error> {
+    let subscriptions = sqlx::query_as!(
+        Subscription,
+        r#"
+        SELECT s.id, s.name, s.status, s.subscribed_at, u.unsubscribe_token AS "unsubscribe_token?"
+        FROM subscriptions s
+        LEFT JOIN unsubscribe_tokens u ON u.subscriber_id = s.id
+        WHERE lower(s.email) = lower($1)
+        ORDER BY s.subscribed_at
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriptions")?;
+    let subscriber_ids: Vec<Uuid> = subscriptions.iter().map(|s| s.id).collect();
+    let confirmation_tokens = sqlx::query_as!(
+        ConfirmationToken,
+        r#"
+        SELECT subscription_token, created_at, expires_at
+        FROM subscription_tokens
+        WHERE subscriber_id = ANY($1)
+        ORDER BY created_at
+        "#,
+        &subscriber_ids,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the confirmation tokens")?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT q.newsletter_issue_id, i.title, q.n_retries, q.execute_after
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE lower(q.subscriber_email) = lower($1)
+        ORDER BY q.execute_after
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending deliveries")?;
+    let sent_deliveries = sqlx::query_as!(
+        SentDelivery,
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.sent_at
+        FROM sent_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE lower(d.subscriber_email) = lower($1)
+        ORDER BY d.sent_at
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the sent deliveries")?;
+    let failed_deliveries = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT f.newsletter_issue_id, i.title, f.n_attempts, f.last_error, f.failed_at
+        FROM failed_deliveries f
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE lower(f.subscriber_email) = lower($1)
+        ORDER BY f.failed_at
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the failed deliveries")?;
+    let suppressed_at = sqlx::query!(
+        "SELECT suppressed_at FROM suppressed_emails WHERE email_hash = $1",
+        suppression_hash(secret, email),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look for a suppression record")?
+    .map(|r| r.suppressed_at);
+    Ok(SubjectData {
+        email: email.to_string(),
+        subscriptions,
+        confirmation_tokens,
+        pending_deliveries,
+        sent_deliveries,
+        failed_deliveries,
+        suppressed_at,
+    })
+}
@@ -0,0 +1,411 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::domain::SubscriptionStatus;
+use crate::utils::{e400, e404, e500};
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersQuery {
+    #[serde(default)]
+    search: String,
+    /// Empty for every status.
+    #[serde(default)]
+    status: String,
+    page: Option<i64>,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+struct SubscriberDetails {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    /// When the latest confirmation link stops working, if there is one.
+    confirmation_expires_at: Option<DateTime<Utc>>,
+}
+
+struct Delivery {
+    title: String,
+    outcome: String,
+    at: DateTime<Utc>,
+    error: Option<String>,
+}
+
+fn flash_html(flash_messages: &IncomingFlashMessages) -> String {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    msg_html
+}
+
+pub async fn subscribers(
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_html(&flash_messages);
+    let status = match query.status.as_str() {
+        "" => None,
+        s => Some(SubscriptionStatus::parse(s).map_err(e400)?),
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let search = query.search.trim();
+
+    let (subscribers, n_subscribers) = search_subscribers(&pool, search, status, page)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/subscribers/{id}">{email}</a></td>
+                <td>{name}</td>
+                <td>{status}</td>
+                <td>{subscribed_at}</td>
+            </tr>"#,
+            id = s.id,
+            email = encode_minimal(&s.email),
+            name = encode_minimal(&s.name),
+            status = s.status,
+            subscribed_at = s.subscribed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    let content_html = if subscribers.is_empty() {
+        "<p>No subscriber matches.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+                <tr>
+                    <th>Email</th>
+                    <th>Name</th>
+                    <th>Status</th>
+                    <th>Subscribed at</th>
+                </tr>
+                {rows_html}
+            </table>"#
+        )
+    };
+
+    let n_pages = ((n_subscribers + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let page_link = |page: i64, label: &str| {
+        format!(
+            r#"<a href="/admin/subscribers?search={search}&status={status}&page={page}">{label}</a>"#,
+            search = urlencoding::encode(search),
+            status = urlencoding::encode(&query.status),
+        )
+    };
+    let mut pagination_html = format!("Page {page} of {n_pages}");
+    if page > 1 {
+        pagination_html = format!(
+            "{} {}",
+            page_link(page - 1, "&lt; Previous"),
+            pagination_html
+        );
+    }
+    if page < n_pages {
+        pagination_html = format!("{} {}", pagination_html, page_link(page + 1, "Next &gt;"));
+    }
+
+    let mut status_options_html = String::new();
+    for option in [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+    ] {
+        writeln!(
+            status_options_html,
+            r#"<option value="{value}"{selected}>{value}</option>"#,
+            value = option.as_str(),
+            selected = if status == Some(option) {
+                " selected"
+            } else {
+                ""
+            },
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Subscribers</title>
+            </head>
+            <body>
+                {msg_html}
+                <form action="/admin/subscribers" method="get">
+                    <label>Search by email or name
+                        <input type="text" name="search" value="{search}">
+                    </label>
+                    <label>Status
+                        <select name="status">
+                            <option value="">any</option>
+                            {status_options_html}
+                        </select>
+                    </label>
+                    <button type="submit">Search</button>
+                </form>
+                <p><a href="/admin/subscribers/import">Import subscribers</a></p>
+                <form action="/admin/subscribers/data" method="get">
+                    <label>Data request for
+                        <input type="email" name="email" required>
+                    </label>
+                    <button type="submit">Download their data</button>
+                    <button type="submit" formaction="/admin/subscribers/erase" formmethod="post">Erase them</button>
+                </form>
+                <p>
+                    Export every subscriber as
+                    <a href="/admin/subscribers/export?format=csv">CSV</a> or
+                    <a href="/admin/subscribers/export?format=json">JSON</a>
+                </p>
+                <p>{n_subscribers} subscribers</p>
+                {content_html}
+                <p>{pagination_html}</p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+            search = encode_minimal(search),
+        )))
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_html(&flash_messages);
+    let subscriber_id = subscriber_id.into_inner();
+    let details = get_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("There is no such subscriber."))?;
+    let deliveries = get_delivery_history(&pool, &details.email)
+        .await
+        .map_err(e500)?;
+
+    let confirmation_html = match (details.status.as_str(), details.confirmation_expires_at) {
+        ("pending_confirmation", Some(expires_at)) if expires_at > Utc::now() => format!(
+            "Waiting for confirmation, the link expires at {}",
+            expires_at.to_rfc3339()
+        ),
+        ("pending_confirmation", _) => "Waiting for confirmation, the link has expired".into(),
+        (status, _) => status.to_string(),
+    };
+
+    let mut rows_html = String::new();
+    for d in &deliveries {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{outcome}</td>
+                <td>{at}</td>
+                <td><pre>{error}</pre></td>
+            </tr>"#,
+            title = encode_minimal(&d.title),
+            outcome = d.outcome,
+            at = d.at.to_rfc3339(),
+            error = encode_minimal(d.error.as_deref().unwrap_or("")),
+        )
+        .unwrap();
+    }
+    let deliveries_html = if deliveries.is_empty() {
+        "<p>No newsletter issue has been sent to this subscriber.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+                <tr>
+                    <th>Issue</th>
+                    <th>Outcome</th>
+                    <th>At</th>
+                    <th>Error</th>
+                </tr>
+                {rows_html}
+            </table>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Subscriber</title>
+            </head>
+            <body>
+                {msg_html}
+                <ul>
+                    <li>Email: {email}</li>
+                    <li>Name: {name}</li>
+                    <li>Subscribed at: {subscribed_at}</li>
+                    <li>Status: {confirmation_html}</li>
+                </ul>
+                <form action="/admin/subscribers/{subscriber_id}/confirm" method="post">
+                    <button type="submit">Confirm</button>
+                </form>
+                <form action="/admin/subscribers/{subscriber_id}/unsubscribe" method="post">
+                    <button type="submit">Unsubscribe</button>
+                </form>
+                <form action="/admin/subscribers/{subscriber_id}/delete" method="post">
+                    <button type="submit">Delete</button>
+                </form>
+                <p><a href="/admin/subscribers/data?email={email_query}">Download everything we hold about them</a></p>
+                <form action="/admin/subscribers/erase" method="post">
+                    <input hidden type="text" name="email" value="{email}">
+                    <button type="submit">Erase them</button>
+                </form>
+                <p>Deliveries:</p>
+                {deliveries_html}
+                <p><a href="/admin/subscribers">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+            email = encode_minimal(&details.email),
+            email_query = urlencoding::encode(&details.email),
+            name = encode_minimal(&details.name),
+            subscribed_at = details.subscribed_at.to_rfc3339(),
+        )))
+}
+
+/// `search` matches anywhere in the email or the name, ignoring case.
+#[tracing::instrument(name = "Search subscribers", skip(pool))]
+async fn search_subscribers(
+    pool: &PgPool,
+    search: &str,
+    status: Option<SubscriptionStatus>,
+    page: i64,
+) -> Result<(Vec<SubscriberRow>, i64), anyhow::Error> {
+    let pattern = format!(
+        "%{}%",
+        search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let status = status.map(|s| s.as_str());
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE (email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+        ORDER BY subscribed_at DESC, id
+        LIMIT $3 OFFSET $4
+        "#,
+        pattern,
+        status,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscribers.")?;
+    let n_subscribers = sqlx::query!(
+        r#"
+        SELECT count(*) AS "count!"
+        FROM subscriptions
+        WHERE (email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+        "#,
+        pattern,
+        status,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers.")?
+    .count;
+    Ok((subscribers, n_subscribers))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    let details = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            (
+                SELECT max(t.expires_at) FROM subscription_tokens t
+                WHERE t.subscriber_id = s.id
+            ) AS confirmation_expires_at
+        FROM subscriptions s
+        WHERE s.id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    Ok(details)
+}
+
+/// Every issue sent, failed or still waiting to be sent to `subscriber_email`,
+/// most recent first.
+#[tracing::instrument(name = "Get delivery history", skip(pool))]
+async fn get_delivery_history(
+    pool: &PgPool,
+    subscriber_email: &str,
+) -> Result<Vec<Delivery>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT
+            i.title,
+            d.outcome AS "outcome!",
+            d.at AS "at!",
+            d.error
+        FROM (
+            SELECT newsletter_issue_id, 'sent' AS outcome, sent_at AS at, NULL AS error
+            FROM sent_deliveries WHERE subscriber_email = $1
+            UNION ALL
+            SELECT newsletter_issue_id, 'failed', failed_at, last_error
+            FROM failed_deliveries WHERE subscriber_email = $1
+            UNION ALL
+            SELECT newsletter_issue_id, 'pending', execute_after, NULL
+            FROM issue_delivery_queue WHERE subscriber_email = $1
+        ) d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        ORDER BY d.at DESC
+        "#,
+        subscriber_email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history.")?;
+    Ok(deliveries)
+}
@@ -0,0 +1,435 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use htmlescape::encode_minimal;
+use sqlx::{Connection, PgPool, Postgres, Transaction};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::gdpr::is_suppressed;
+use crate::configuration::SubscriptionSettings;
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus, SubscriptionToken,
+    UnsubscribeToken,
+};
+use crate::issue_delivery_worker::{enqueue_confirmation_email, notify_workers};
+use crate::routes::{insert_subscriber, select_subscriber, store_token, store_unsubscribe_token};
+use crate::startup::HmacSecret;
+use crate::utils::{e400, see_other};
+
+/// The largest CSV file we accept, in bytes.
+const MAX_FILE_SIZE: usize = 10 * 1024 * 1024;
+
+/// How many rows are inserted in each transaction.
+const BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy)]
+enum ImportMode {
+    /// The other provider already collected their consent.
+    Confirmed,
+    /// Everyone gets a confirmation email, as if they had subscribed here.
+    DoubleOptIn,
+}
+
+impl ImportMode {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "confirmed" => Ok(Self::Confirmed),
+            "double_opt_in" => Ok(Self::DoubleOptIn),
+            other => Err(format!("{} is not a valid import mode.", other)),
+        }
+    }
+}
+
+enum RowOutcome {
+    Imported,
+    Duplicate,
+    /// The address was erased at their request.
+    Suppressed,
+    Invalid(String),
+    /// Something went wrong on our side: importing the file again retries it.
+    Failed,
+}
+
+impl RowOutcome {
+    fn describe(&self) -> String {
+        match self {
+            RowOutcome::Imported => "Imported".into(),
+            RowOutcome::Duplicate => "Duplicate".into(),
+            RowOutcome::Suppressed => "Skipped: erased at their request".into(),
+            RowOutcome::Invalid(reason) => format!("Invalid: {}", reason),
+            RowOutcome::Failed => "Failed: an unexpected error occurred".into(),
+        }
+    }
+}
+
+struct RowReport {
+    line: u64,
+    email: String,
+    outcome: RowOutcome,
+}
+
+pub async fn import_subscribers_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Import subscribers</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Upload a CSV file with an <code>email</code> and a <code>name</code> column.</p>
+                <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+                    <input type="file" name="file" accept=".csv,text/csv" required>
+                    <br>
+                    <label>
+                        <input type="radio" name="mode" value="confirmed" checked>
+                        Import them as confirmed subscribers
+                    </label>
+                    <br>
+                    <label>
+                        <input type="radio" name="mode" value="double_opt_in">
+                        Send each of them a confirmation email
+                    </label>
+                    <br>
+                    <button type="submit">Import</button>
+                </form>
+                <p><a href="/admin/subscribers">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+        )))
+}
+
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip_all,
+    fields(mode = tracing::field::Empty)
+)]
+pub async fn import_subscribers(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+    subscription_settings: web::Data<SubscriptionSettings>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut file = None;
+    let mut mode = None;
+    while let Some(field) = payload.try_next().await.map_err(e400)? {
+        match field.name() {
+            Some("file") => file = Some(read_field(field).await?),
+            Some("mode") => {
+                let value = read_field(field).await?;
+                let value = String::from_utf8_lossy(&value);
+                mode = Some(ImportMode::parse(value.trim()).map_err(e400)?);
+            }
+            _ => {}
+        }
+    }
+    let mode = mode.unwrap_or(ImportMode::Confirmed);
+    tracing::Span::current().record("mode", tracing::field::debug(mode));
+    let file = match file {
+        Some(file) if !file.is_empty() => file,
+        _ => {
+            FlashMessage::error("Choose a CSV file to import.").send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(file.as_slice());
+    let headers = reader.headers().map_err(e400)?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let (Some(email_column), Some(name_column)) = (column("email"), column("name")) else {
+        FlashMessage::error("The file must have an email and a name column.").send();
+        return Ok(see_other("/admin/subscribers/import"));
+    };
+
+    let mut seen = HashSet::new();
+    let mut reports = Vec::new();
+    // The rows left to insert, along with the index of their report
+    let mut new_subscribers = Vec::new();
+    for record in reader.records() {
+        let (line, record) = match record {
+            Ok(record) => (record.position().map_or(0, |p| p.line()), record),
+            Err(e) => {
+                reports.push(RowReport {
+                    line: e.position().map_or(0, |p| p.line()),
+                    email: String::new(),
+                    outcome: RowOutcome::Invalid(e.to_string()),
+                });
+                continue;
+            }
+        };
+        let email = record.get(email_column).unwrap_or_default().to_string();
+        let name = record.get(name_column).unwrap_or_default().to_string();
+        // Compared without their case, like existing subscribers
+        let outcome = if !seen.insert(email.to_lowercase()) {
+            RowOutcome::Duplicate
+        } else {
+            match parse_row(email.clone(), name) {
+                Ok(new_subscriber) => {
+                    new_subscribers.push((reports.len(), new_subscriber));
+                    // Until the batch it belongs to is committed
+                    RowOutcome::Failed
+                }
+                Err(reason) => RowOutcome::Invalid(reason),
+            }
+        };
+        reports.push(RowReport {
+            line,
+            email,
+            outcome,
+        });
+    }
+
+    for batch in new_subscribers.chunks(BATCH_SIZE) {
+        match import_batch(&pool, &subscription_settings, &hmac_secret, batch, mode).await {
+            Ok(outcomes) => {
+                for ((index, _), outcome) in batch.iter().zip(outcomes) {
+                    reports[*index].outcome = outcome;
+                }
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to import a batch of subscribers"
+                );
+            }
+        }
+    }
+
+    Ok(import_report(&reports))
+}
+
+async fn read_field(mut field: Field) -> Result<Vec<u8>, actix_web::Error> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(e400)? {
+        if data.len() + chunk.len() > MAX_FILE_SIZE {
+            return Err(e400("The file is too large."));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+fn parse_row(email: String, name: String) -> Result<NewSubscriber, String> {
+    Ok(NewSubscriber {
+        email: SubscriberEmail::parse(email)?,
+        name: SubscriberName::parse(name)?,
+    })
+}
+
+/// Insert a batch of subscribers in a single transaction. A row that fails
+/// is rolled back to its savepoint and reported, without affecting the others.
+#[tracing::instrument(
+    name = "Import a batch of subscribers",
+    skip_all,
+    fields(n_rows = batch.len())
+)]
+async fn import_batch(
+    pool: &PgPool,
+    subscription_settings: &SubscriptionSettings,
+    hmac_secret: &HmacSecret,
+    batch: &[(usize, NewSubscriber)],
+    mode: ImportMode,
+) -> Result<Vec<RowOutcome>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut outcomes = Vec::with_capacity(batch.len());
+    for (_, new_subscriber) in batch {
+        let mut savepoint = transaction
+            .begin()
+            .await
+            .context("Failed to create a savepoint for an imported subscriber")?;
+        match import_subscriber(
+            &mut savepoint,
+            subscription_settings,
+            hmac_secret,
+            new_subscriber,
+            mode,
+        )
+        .await
+        {
+            Ok(outcome) => {
+                savepoint
+                    .commit()
+                    .await
+                    .context("Failed to release the savepoint of an imported subscriber")?;
+                outcomes.push(outcome);
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    subscriber_email = %new_subscriber.email.as_ref(),
+                    "Failed to import a subscriber"
+                );
+                savepoint
+                    .rollback()
+                    .await
+                    .context("Failed to roll back the savepoint of an imported subscriber")?;
+                outcomes.push(RowOutcome::Failed);
+            }
+        }
+    }
+    if let ImportMode::DoubleOptIn = mode {
+        notify_workers(&mut *transaction)
+            .await
+            .context("Failed to notify the workers of the queued confirmation emails")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers")?;
+    Ok(outcomes)
+}
+
+#[tracing::instrument(
+    name = "Import a subscriber",
+    skip(transaction, subscription_settings, hmac_secret, new_subscriber),
+    fields(subscriber_email = %new_subscriber.email.as_ref())
+)]
+async fn import_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_settings: &SubscriptionSettings,
+    hmac_secret: &HmacSecret,
+    new_subscriber: &NewSubscriber,
+    mode: ImportMode,
+) -> Result<RowOutcome, anyhow::Error> {
+    if is_suppressed(
+        &mut **transaction,
+        hmac_secret,
+        new_subscriber.email.as_ref(),
+    )
+    .await
+    .context("Failed to look for a suppression record")?
+    {
+        return Ok(RowOutcome::Suppressed);
+    }
+    if select_subscriber(&mut **transaction, new_subscriber)
+        .await
+        .context("Failed to look for an existing subscriber")?
+        .is_some()
+    {
+        return Ok(RowOutcome::Duplicate);
+    }
+    let status = match mode {
+        ImportMode::Confirmed => SubscriptionStatus::Confirmed,
+        ImportMode::DoubleOptIn => SubscriptionStatus::PendingConfirmation,
+    };
+    let subscriber_id = insert_subscriber(transaction, new_subscriber, status)
+        .await
+        .context("Failed to insert an imported subscriber")?;
+    store_unsubscribe_token(transaction, subscriber_id, &UnsubscribeToken::generate())
+        .await
+        .context("Failed to store unsubscribe token into the database")?;
+    if let ImportMode::DoubleOptIn = mode {
+        // Sent by the delivery worker, within the email provider's rate limits
+        let token = SubscriptionToken::generate();
+        store_token(
+            transaction,
+            subscriber_id,
+            &token,
+            subscription_settings.confirmation_token_ttl(),
+        )
+        .await
+        .context("Failed to store confirmation token into the database")?;
+        enqueue_confirmation_email(transaction, &token)
+            .await
+            .context("Failed to queue the confirmation email")?;
+    }
+    Ok(RowOutcome::Imported)
+}
+
+fn import_report(reports: &[RowReport]) -> HttpResponse {
+    let n_imported = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, RowOutcome::Imported))
+        .count();
+    let n_duplicates = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, RowOutcome::Duplicate))
+        .count();
+    let n_invalid = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, RowOutcome::Invalid(_)))
+        .count();
+    let n_suppressed = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, RowOutcome::Suppressed))
+        .count();
+    let suppressed_html = if n_suppressed > 0 {
+        format!("<p>{n_suppressed} skipped because they were erased at their request.</p>")
+    } else {
+        String::new()
+    };
+    let n_failed = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, RowOutcome::Failed))
+        .count();
+    let failed_html = if n_failed > 0 {
+        format!("<p>{n_failed} failed. Import the file again to retry them.</p>")
+    } else {
+        String::new()
+    };
+    let mut rows_html = String::new();
+    for r in reports {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{line}</td>
+                <td>{email}</td>
+                <td>{outcome}</td>
+            </tr>"#,
+            line = r.line,
+            email = encode_minimal(&r.email),
+            outcome = encode_minimal(&r.outcome.describe()),
+        )
+        .unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Import report</title>
+            </head>
+            <body>
+                <p>{n_imported} imported, {n_duplicates} duplicates, {n_invalid} invalid.</p>
+                {suppressed_html}
+                {failed_html}
+                <table>
+                    <tr>
+                        <th>Line</th>
+                        <th>Email</th>
+                        <th>Outcome</th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p><a href="/admin/subscribers">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+        ))
+}
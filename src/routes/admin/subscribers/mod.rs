@@ -0,0 +1,10 @@
+mod export;
+mod gdpr;
+mod get;
+mod import;
+mod post;
+pub use export::export_subscribers;
+pub use gdpr::{erase_subject, subject_data};
+pub use get::{subscriber_details, subscribers};
+pub use import::{import_subscribers, import_subscribers_form};
+pub use post::{confirm_subscriber, delete_subscriber, unsubscribe_subscriber};
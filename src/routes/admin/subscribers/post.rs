@@ -0,0 +1,163 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{StatusChange, SubscriptionStatus};
+use crate::routes::{cancel_pending_deliveries_to, change_subscription_status};
+use crate::utils::{e500, see_other};
+
+#[tracing::instrument(name = "Confirm a subscriber on their behalf", skip(pool))]
+pub async fn confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(_) = lock_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(no_such_subscriber());
+    };
+    let change = change_subscription_status(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber")
+        .map_err(e500)?;
+    match change {
+        StatusChange::Applied => FlashMessage::info("The subscriber has been confirmed.").send(),
+        StatusChange::AlreadyApplied => {
+            FlashMessage::info("The subscriber had already confirmed.").send()
+        }
+        // Only they can take that back by subscribing again
+        StatusChange::NotAllowed => {
+            FlashMessage::error("People who unsubscribed cannot be confirmed.").send()
+        }
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber on their behalf", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(email) = lock_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(no_such_subscriber());
+    };
+    change_subscription_status(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+    .map_err(e500)?;
+    cancel_pending_deliveries_to(&mut transaction, &email)
+        .await
+        .context("Failed to cancel the deliveries still pending for the subscriber")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(email) = lock_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(no_such_subscriber());
+    };
+    cancel_pending_deliveries_to(&mut transaction, &email)
+        .await
+        .context("Failed to cancel the deliveries still pending for the subscriber")
+        .map_err(e500)?;
+    delete_subscription(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber")
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} has been deleted.", email)).send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+fn no_such_subscriber() -> HttpResponse {
+    FlashMessage::error("There is no such subscriber.").send();
+    see_other("/admin/subscribers")
+}
+
+/// Lock the subscriber for the rest of `transaction`, returning their email.
+async fn lock_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to retrieve the subscriber")?;
+    Ok(row.map(|r| r.email))
+}
+
+pub(super) async fn delete_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"DELETE FROM unsubscribe_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id);
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the subscriber")?;
+    Ok(())
+}
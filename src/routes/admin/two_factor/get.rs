@@ -0,0 +1,138 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use base64::Engine;
+use htmlescape::encode_minimal;
+use qrcode::{render::svg, QrCode};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::authentication::{generate_totp_secret, totp, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+struct TwoFactorStatus {
+    username: String,
+    enabled: bool,
+    recovery_codes_left: i64,
+}
+
+/// Lets users turn two-factor authentication on, with a new secret for their
+/// authenticator, or off.
+pub async fn two_factor_settings(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let status = get_two_factor_status(**user_id, &pool)
+        .await
+        .map_err(e500)?;
+
+    let settings_html = if status.enabled {
+        format!(
+            r#"<p>Two-factor authentication is on.</p>
+            <p>{recovery_codes_left} unused recovery codes left.</p>
+            <form action="/admin/two_factor/recovery_codes" method="post">
+                <label>Current password
+                    <input type="password" name="current_password" required>
+                </label>
+                <button type="submit">Get new recovery codes</button>
+            </form>
+            <form action="/admin/two_factor/disable" method="post">
+                <label>Current password
+                    <input type="password" name="current_password" required>
+                </label>
+                <button type="submit">Turn off two-factor authentication</button>
+            </form>"#,
+            recovery_codes_left = status.recovery_codes_left,
+        )
+    } else {
+        // Keep the same secret until it is confirmed: reloading the page must
+        // not break what was already scanned
+        let secret = match session.get_totp_enrolment_secret().map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = generate_totp_secret();
+                session
+                    .insert_totp_enrolment_secret(&secret)
+                    .map_err(e500)?;
+                secret
+            }
+        };
+        let provisioning_uri = totp(&secret, &status.username).map_err(e500)?.get_url();
+        let qr_code = QrCode::new(&provisioning_uri)
+            .context("Failed to encode the provisioning URI as a QR code")
+            .map_err(e500)?
+            .render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .build();
+        format!(
+            r#"<p>Two-factor authentication is off.</p>
+            <p>Scan this QR code with your authenticator app:</p>
+            <img src="data:image/svg+xml;base64,{qr_code}" alt="QR code of {provisioning_uri}">
+            <p>Or enter this key in it: <code>{secret}</code></p>
+            <form action="/admin/two_factor" method="post">
+                <label>Code shown by your authenticator app
+                    <input type="text" name="code" autocomplete="one-time-code" required>
+                </label>
+                <button type="submit">Turn on two-factor authentication</button>
+            </form>"#,
+            qr_code = base64::engine::general_purpose::STANDARD.encode(qr_code),
+            provisioning_uri = encode_minimal(&provisioning_uri),
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Two-factor authentication</title>
+            </head>
+            <body>
+                {msg_html}
+                {settings_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get two-factor authentication status", skip(pool))]
+async fn get_two_factor_status(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<TwoFactorStatus, anyhow::Error> {
+    let status = sqlx::query_as!(
+        TwoFactorStatus,
+        r#"
+        SELECT
+            username,
+            totp_secret IS NOT NULL AS "enabled!",
+            (
+                SELECT count(*) FROM recovery_codes r
+                WHERE r.user_id = u.user_id AND r.used_at IS NULL
+            ) AS "recovery_codes_left!"
+        FROM users u
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the two-factor authentication status of a user.")?;
+    Ok(status)
+}
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::two_factor_settings;
+pub use post::{disable_two_factor, enable_two_factor, regenerate_recovery_codes};
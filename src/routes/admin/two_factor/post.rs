@@ -0,0 +1,199 @@
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Executor, PgPool};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::authentication::{
+    matching_time_step, replace_recovery_codes, totp, validate_credentials, AuthError, Credentials,
+    PasswordHashing, UserId,
+};
+use crate::domain::RecoveryCode;
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct EnableFormData {
+    code: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordFormData {
+    current_password: Secret<String>,
+}
+
+/// Turn two-factor authentication on, once the user proved their authenticator
+/// has the secret they were shown.
+#[tracing::instrument(name = "Enable two-factor authentication", skip(form, session, pool))]
+pub async fn enable_two_factor(
+    form: web::Form<EnableFormData>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    let Some(secret) = session.get_totp_enrolment_secret().map_err(e500)? else {
+        FlashMessage::error("Scan the QR code again, then enter the code it shows.").send();
+        return Ok(see_other("/admin/two_factor"));
+    };
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    let totp = totp(&secret, &username).map_err(e500)?;
+    let Some(step) = matching_time_step(&totp, form.code.expose_secret()).map_err(e500)? else {
+        FlashMessage::error("The code is incorrect. Check your authenticator app's clock.").send();
+        return Ok(see_other("/admin/two_factor"));
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let query = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2, totp_last_used_step = $3
+        WHERE user_id = $1 AND totp_secret IS NULL
+        "#,
+        user_id,
+        secret,
+        step,
+    );
+    let n_updated = transaction
+        .execute(query)
+        .await
+        .context("Failed to store the TOTP secret of a user")
+        .map_err(e500)?
+        .rows_affected();
+    if n_updated == 0 {
+        FlashMessage::error("Two-factor authentication is already on.").send();
+        return Ok(see_other("/admin/two_factor"));
+    }
+    let recovery_codes = replace_recovery_codes(&mut transaction, user_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication")
+        .map_err(e500)?;
+    session.remove_totp_enrolment_secret();
+    Ok(recovery_codes_page(
+        "Two-factor authentication is on.",
+        &recovery_codes,
+    ))
+}
+
+#[tracing::instrument(name = "Turn off two-factor authentication", skip(form, pool, hashing))]
+pub async fn disable_two_factor(
+    form: web::Form<PasswordFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    if !check_current_password(user_id, form.0.current_password, &hashing, &pool).await? {
+        FlashMessage::error("The current password is incorrect.").send();
+        return Ok(see_other("/admin/two_factor"));
+    }
+    crate::authentication::disable_two_factor(user_id, &pool)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("Two-factor authentication is off.").send();
+    Ok(see_other("/admin/two_factor"))
+}
+
+/// New recovery codes, for users who used up or lost theirs. The old ones stop
+/// working.
+#[tracing::instrument(name = "Regenerate recovery codes", skip(form, pool, hashing))]
+pub async fn regenerate_recovery_codes(
+    form: web::Form<PasswordFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = **user_id;
+    if !check_current_password(user_id, form.0.current_password, &hashing, &pool).await? {
+        FlashMessage::error("The current password is incorrect.").send();
+        return Ok(see_other("/admin/two_factor"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Lock the user: turning two-factor authentication off at the same time
+    // would leave codes behind
+    let enabled = sqlx::query!(
+        "SELECT totp_secret IS NOT NULL AS \"enabled!\" FROM users WHERE user_id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to check if two-factor authentication is on")
+    .map_err(e500)?
+    .enabled;
+    if !enabled {
+        FlashMessage::error("Two-factor authentication is off.").send();
+        return Ok(see_other("/admin/two_factor"));
+    }
+    let recovery_codes = replace_recovery_codes(&mut transaction, user_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to regenerate recovery codes")
+        .map_err(e500)?;
+    Ok(recovery_codes_page(
+        "Your previous recovery codes no longer work.",
+        &recovery_codes,
+    ))
+}
+
+async fn check_current_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+    pool: &PgPool,
+) -> Result<bool, actix_web::Error> {
+    let username = get_username(user_id, pool).await.map_err(e500)?;
+    let credentials = Credentials { username, password };
+    match validate_credentials(credentials, hashing, pool).await {
+        Ok(_) => Ok(true),
+        Err(AuthError::InvalidCredentials(_)) => Ok(false),
+        Err(e) => Err(e500(e)),
+    }
+}
+
+/// Recovery codes are only stored hashed: this page is the one chance to see them.
+fn recovery_codes_page(message: &str, recovery_codes: &[RecoveryCode]) -> HttpResponse {
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", code.as_ref()).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Recovery codes</title>
+            </head>
+            <body>
+                <p>{message}</p>
+                <p>Keep these recovery codes somewhere safe. Each of them lets you
+                log in once without your authenticator app. They will not be shown again.</p>
+                <ul>
+                    {codes_html}
+                </ul>
+                <p><a href="/admin/two_factor">Done</a></p>
+            </body>
+            </html>"#,
+        ))
+}
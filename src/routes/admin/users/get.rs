@@ -0,0 +1,202 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::authentication::{Role, UserId};
+use crate::utils::e500;
+
+struct User {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    disabled_at: Option<DateTime<Utc>>,
+    two_factor: bool,
+}
+
+struct Invitation {
+    username: String,
+    email: String,
+    role: String,
+    expires_at: DateTime<Utc>,
+}
+
+pub async fn users(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let current_user_id = **user_id;
+    let users = get_users(&pool).await.map_err(e500)?;
+    let invitations = get_pending_invitations(&pool).await.map_err(e500)?;
+
+    let mut users_html = String::new();
+    for u in &users {
+        let actions_html = if u.user_id == current_user_id {
+            "(you)".to_string()
+        } else {
+            let (toggle_action, toggle_label) = match u.disabled_at {
+                Some(_) => ("enable", "Enable"),
+                None => ("disable", "Disable"),
+            };
+            let reset_two_factor_html = if u.two_factor {
+                format!(
+                    r#"<form action="/admin/users/{id}/reset_two_factor" method="post">
+                    <button type="submit">Turn off two-factor authentication</button>
+                </form>"#,
+                    id = u.user_id,
+                )
+            } else {
+                String::new()
+            };
+            format!(
+                r#"{reset_two_factor_html}
+                <form action="/admin/users/{id}/{toggle_action}" method="post">
+                    <button type="submit">{toggle_label}</button>
+                </form>
+                <form action="/admin/users/{id}/delete" method="post">
+                    <button type="submit">Delete</button>
+                </form>"#,
+                id = u.user_id,
+            )
+        };
+        writeln!(
+            users_html,
+            r#"<tr>
+                <td>{username}</td>
+                <td>{email}</td>
+                <td>{role}</td>
+                <td>{status}</td>
+                <td>{actions_html}</td>
+            </tr>"#,
+            username = encode_minimal(&u.username),
+            email = encode_minimal(u.email.as_deref().unwrap_or("-")),
+            role = u.role,
+            status = match (u.disabled_at, u.two_factor) {
+                (Some(disabled_at), _) => format!("disabled since {}", disabled_at.to_rfc3339()),
+                (None, true) => "active, two-factor".into(),
+                (None, false) => "active".into(),
+            },
+        )
+        .unwrap();
+    }
+
+    let mut invitations_html = String::new();
+    for i in &invitations {
+        writeln!(
+            invitations_html,
+            "<li>{username} ({email}), {role}, until {expires_at}</li>",
+            username = encode_minimal(&i.username),
+            email = encode_minimal(&i.email),
+            role = i.role,
+            expires_at = i.expires_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    if invitations_html.is_empty() {
+        invitations_html = "<li>None</li>".into();
+    }
+
+    let mut role_options_html = String::new();
+    for role in [Role::Viewer, Role::Editor, Role::Owner] {
+        writeln!(
+            role_options_html,
+            r#"<option value="{role}">{role}</option>"#,
+            role = role.as_str()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Users</title>
+            </head>
+            <body>
+                {msg_html}
+                <table>
+                    <tr>
+                        <th>Username</th>
+                        <th>Email</th>
+                        <th>Role</th>
+                        <th>Status</th>
+                        <th></th>
+                    </tr>
+                    {users_html}
+                </table>
+                <p>Pending invitations:</p>
+                <ul>
+                    {invitations_html}
+                </ul>
+                <form action="/admin/users/invite" method="post">
+                    <label>Username
+                        <input type="text" name="username" required>
+                    </label>
+                    <label>Email
+                        <input type="email" name="email" required>
+                    </label>
+                    <label>Role
+                        <select name="role">
+                            {role_options_html}
+                        </select>
+                    </label>
+                    <button type="submit">Invite</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT
+            user_id, username, email, role, disabled_at,
+            totp_secret IS NOT NULL AS "two_factor!"
+        FROM users
+        ORDER BY username
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve users.")?;
+    Ok(users)
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<Invitation>, anyhow::Error> {
+    let invitations = sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT username, email, role, expires_at
+        FROM user_invitations
+        WHERE expires_at > now()
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve pending invitations.")?;
+    Ok(invitations)
+}
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::users;
+pub use post::{delete_user, disable_user, enable_user, invite_user, reset_two_factor};
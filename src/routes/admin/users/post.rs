@@ -0,0 +1,267 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{Executor, PgPool};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::authentication::{disable_two_factor, Role, UserId};
+use crate::domain::{InvitationToken, SubscriberEmail};
+use crate::email_client::{EmailSender, SendEmailError};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e400, e500, see_other};
+
+/// How long people have to accept an invitation.
+const INVITATION_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(serde::Deserialize)]
+pub struct InvitationFormData {
+    username: String,
+    email: String,
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(form, pool, email_client, base_url),
+    fields(username = %form.username, role = %form.role)
+)]
+pub async fn invite_user(
+    form: web::Form<InvitationFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let role = Role::parse(&form.role).map_err(e400)?;
+    let username = form.username.trim();
+    if username.is_empty() {
+        FlashMessage::error("Choose a username for them.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let email = match SubscriberEmail::parse(form.email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let username_taken = sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_optional(pool.get_ref())
+        .await
+        .context("Failed to look for an existing user")
+        .map_err(e500)?
+        .is_some();
+    if username_taken {
+        FlashMessage::error(format!("{} is already taken.", username)).send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let token = InvitationToken::generate();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Inviting them again replaces their previous invitation
+    let query = sqlx::query!(
+        "DELETE FROM user_invitations WHERE username = $1 OR expires_at < now()",
+        username
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete previous invitations")
+        .map_err(e500)?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (
+            token_hash, username, email, role, created_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, now(), now() + make_interval(secs => $5))
+        "#,
+        token.hash(),
+        username,
+        email.as_ref(),
+        role.as_str(),
+        INVITATION_TTL.as_secs_f64(),
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to store the invitation")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to invite a user")
+        .map_err(e500)?;
+
+    if let Err(e) = send_invitation_email(email_client.as_ref(), &email, &base_url.0, &token).await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send an invitation email"
+        );
+        FlashMessage::error(format!(
+            "The invitation email could not be sent to {}. Invite them again later.",
+            email.as_ref()
+        ))
+        .send();
+        return Ok(see_other("/admin/users"));
+    }
+    FlashMessage::info(format!(
+        "An invitation has been sent to {}.",
+        email.as_ref()
+    ))
+    .send();
+    Ok(see_other("/admin/users"))
+}
+
+async fn send_invitation_email(
+    email_client: &dyn EmailSender,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    token: &InvitationToken,
+) -> Result<(), SendEmailError> {
+    let invitation_link = format!("{}/invitations/{}", base_url, token.as_ref());
+    let plain_body = format!(
+        "You have been invited to manage our newsletter.\n\
+        Visit {} to choose your password.",
+        invitation_link
+    );
+    let html_body = format!(
+        "You have been invited to manage our newsletter.<br />\
+        Click <a href=\"{}\">here</a> to choose your password.",
+        invitation_link
+    );
+    email_client
+        .send_email(recipient, "You are invited", &html_body, &plain_body)
+        .await
+}
+
+#[tracing::instrument(name = "Disable a user", skip(pool, current_user_id))]
+pub async fn disable_user(
+    user_id: web::Path<Uuid>,
+    current_user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    if user_id == **current_user_id {
+        FlashMessage::error("You cannot disable your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let n_disabled = sqlx::query!(
+        "UPDATE users SET disabled_at = now() WHERE user_id = $1 AND disabled_at IS NULL",
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to disable a user")
+    .map_err(e500)?
+    .rows_affected();
+    if n_disabled == 0 {
+        FlashMessage::error("The user does not exist or is already disabled.").send();
+    } else {
+        FlashMessage::info("The user has been disabled.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Enable a user", skip(pool))]
+pub async fn enable_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_enabled = sqlx::query!(
+        "UPDATE users SET disabled_at = NULL WHERE user_id = $1 AND disabled_at IS NOT NULL",
+        user_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to enable a user")
+    .map_err(e500)?
+    .rows_affected();
+    if n_enabled == 0 {
+        FlashMessage::error("The user does not exist or is already enabled.").send();
+    } else {
+        FlashMessage::info("The user has been enabled.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+/// For users who lost both their authenticator and their recovery codes: they
+/// can log in with their password alone, then set it up again.
+#[tracing::instrument(name = "Reset the two-factor authentication of a user", skip(pool))]
+pub async fn reset_two_factor(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let was_enabled = disable_two_factor(user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?;
+    if was_enabled {
+        FlashMessage::info("Two-factor authentication has been turned off for the user.").send();
+    } else {
+        FlashMessage::error("The user does not exist or does not use two-factor authentication.")
+            .send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Delete a user", skip(pool, current_user_id))]
+pub async fn delete_user(
+    user_id: web::Path<Uuid>,
+    current_user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    if user_id == **current_user_id {
+        FlashMessage::error("You cannot delete your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let query = sqlx::query!("DELETE FROM idempotency WHERE user_id = $1", user_id);
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the saved responses of a user")
+        .map_err(e500)?;
+    let query = sqlx::query!(
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        user_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the password reset tokens of a user")
+        .map_err(e500)?;
+    let query = sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id);
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the recovery codes of a user")
+        .map_err(e500)?;
+    let query = sqlx::query!("DELETE FROM users WHERE user_id = $1", user_id);
+    let n_deleted = transaction
+        .execute(query)
+        .await
+        .context("Failed to delete a user")
+        .map_err(e500)?
+        .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a user")
+        .map_err(e500)?;
+    if n_deleted == 0 {
+        FlashMessage::error("There is no such user.").send();
+    } else {
+        FlashMessage::info("The user has been deleted.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
@@ -0,0 +1,77 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::InvitationToken;
+use crate::utils::{e404, e500};
+
+pub async fn invitation_form(
+    invitation_token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation_token = InvitationToken::parse(invitation_token.into_inner())
+        .map_err(|_| e404("This invitation is not valid or has expired."))?;
+    let username = sqlx::query!(
+        r#"
+        SELECT username FROM user_invitations
+        WHERE token_hash = $1 AND expires_at > now()
+        "#,
+        invitation_token.hash(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the invitation")
+    .map_err(e500)?
+    .ok_or_else(|| e404("This invitation is not valid or has expired."))?
+    .username;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Welcome</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Welcome {username}! Choose a password to finish setting up your account.</p>
+                <form action="/invitations/{token}" method="post">
+                    <label>Password
+                        <input
+                            type="password"
+                            placeholder="Enter your password"
+                            name="new_password"
+                        >
+                    </label>
+                    <br>
+                    <label>Confirm password
+                        <input
+                            type="password"
+                            placeholder="Type the password again"
+                            name="new_password_check"
+                        >
+                    </label>
+                    <br>
+                    <button type="submit">Create my account</button>
+                </form>
+            </body>
+            </html>"#,
+            username = encode_minimal(&username),
+            token = invitation_token.as_ref(),
+        )))
+}
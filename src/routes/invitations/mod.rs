@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::invitation_form;
+pub use post::accept_invitation;
@@ -0,0 +1,92 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Executor, PgPool};
+
+use crate::authentication::{check_password_strength, create_user, PasswordHashing, Role};
+use crate::domain::InvitationToken;
+use crate::utils::{e404, e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(name = "Accept an invitation", skip_all)]
+pub async fn accept_invitation(
+    invitation_token: web::Path<String>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation_token = InvitationToken::parse(invitation_token.into_inner())
+        .map_err(|_| e404("This invitation is not valid or has expired."))?;
+    let form_location = format!("/invitations/{}", invitation_token.as_ref());
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&form_location));
+    }
+    if let Err(e) = check_password_strength(&form.new_password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&form_location));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Locked, so the invitation cannot be accepted twice
+    let invitation = sqlx::query!(
+        r#"
+        SELECT username, email, role FROM user_invitations
+        WHERE token_hash = $1 AND expires_at > now()
+        FOR UPDATE
+        "#,
+        invitation_token.hash(),
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the invitation")
+    .map_err(e500)?
+    .ok_or_else(|| e404("This invitation is not valid or has expired."))?;
+    let role = Role::parse(&invitation.role)
+        .map_err(anyhow::Error::msg)
+        .map_err(e500)?;
+    let user_id = create_user(
+        &mut transaction,
+        &invitation.username,
+        &invitation.email,
+        role,
+        form.0.new_password,
+        &hashing,
+    )
+    .await
+    .map_err(e500)?;
+    if user_id.is_none() {
+        FlashMessage::error(
+            "Someone already uses this username or email address. Ask for a new invitation.",
+        )
+        .send();
+        return Ok(see_other(&form_location));
+    }
+    let query = sqlx::query!(
+        "DELETE FROM user_invitations WHERE token_hash = $1",
+        invitation_token.hash()
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to delete the accepted invitation")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept an invitation")
+        .map_err(e500)?;
+    FlashMessage::info("Your account is ready, you can log in.").send();
+    Ok(see_other("/login"))
+}
@@ -1,10 +1,18 @@
 use actix_web::error::InternalError;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::Secret;
 use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
 
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{
+    get_session_generation, has_two_factor, validate_credentials, AuthError, Credentials,
+    PasswordHashing,
+};
+use crate::client_ip::TrustedProxies;
+use crate::login_throttle::LoginThrottle;
 use crate::session_state::TypedSession;
 use crate::utils::{error_chain_fmt, see_other};
 
@@ -15,30 +23,68 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(request, form, pool, hashing, session, login_throttle, trusted_proxies),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
     session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    trusted_proxies: web::Data<TrustedProxies>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
+    let username = credentials.username.clone();
+    let ip = trusted_proxies.client_ip(&request).map(|ip| ip.to_string());
 
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &pool).await {
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    // Checked before running argon2, and the same whether the username
+    // exists or not
+    let blocked_for = login_throttle
+        .blocked_for(&username, ip.as_deref())
+        .await
+        .context("Failed to check the failed login attempts")
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if let Some(retry_after) = blocked_for {
+        return Err(login_redirect(LoginError::TooManyAttempts { retry_after }));
+    }
+    match validate_credentials(credentials, &hashing, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let two_factor = has_two_factor(user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            if two_factor {
+                session
+                    .insert_pending_user_id(user_id)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                // Their failures are only forgotten once the code is right
+                return Ok(see_other("/login/two_factor"));
+            }
+            login_throttle
+                .forget(&username)
+                .await
+                .context("Failed to reset the failed login attempts")
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            start_session(&session, user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             Ok(see_other("/admin/dashboard"))
         }
         Err(e) => {
+            if let AuthError::InvalidCredentials(_) = e {
+                login_throttle
+                    .record_failure(&username, ip.as_deref())
+                    .await
+                    .context("Failed to record a failed login attempt")
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            }
             let e = match e {
                 AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
@@ -48,7 +94,20 @@ pub async fn login(
     }
 }
 
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+/// Log `user_id` in, once they proved who they are.
+pub(super) async fn start_session(
+    session: &TypedSession,
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let session_generation = get_session_generation(user_id, pool).await?;
+    session.remove_pending_user_id();
+    session.insert_user_id(user_id)?;
+    session.insert_session_generation(session_generation)?;
+    Ok(())
+}
+
+pub(super) fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = see_other("/login");
     InternalError::from_response(e, response)
@@ -58,6 +117,11 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error(
+        "Too many failed login attempts. Try again in {} seconds.",
+        retry_after.as_millis().div_ceil(1000)
+    )]
+    TooManyAttempts { retry_after: Duration },
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
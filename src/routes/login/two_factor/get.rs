@@ -0,0 +1,52 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use std::fmt::Write;
+
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+pub async fn second_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let mut error_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Two-factor authentication</title>
+                </head>
+                <body>
+                    {error_html}
+                    <form action="/login/two_factor" method="post">
+                        <label>Code from your authenticator app, or one of your recovery codes
+                            <input
+                                type="text"
+                                name="code"
+                                autocomplete="one-time-code"
+                                required
+                            >
+                        </label>
+                        <button type="submit">Log in</button>
+                    </form>
+                    <p><a href="/login">Log in as someone else</a></p>
+                </body>
+            </html>"#,
+        )))
+}
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::second_factor_form;
+pub use post::login_second_factor;
@@ -0,0 +1,99 @@
+use actix_web::error::InternalError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::authentication::verify_second_factor;
+use crate::client_ip::TrustedProxies;
+use crate::login_throttle::LoginThrottle;
+use crate::routes::get_username;
+use crate::routes::login::post::{login_redirect, start_session, LoginError};
+use crate::session_state::TypedSession;
+use crate::utils::see_other;
+
+/// Wrong codes allowed before the password has to be entered again.
+const MAX_SECOND_FACTOR_ATTEMPTS: u32 = 5;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Log in with a second factor",
+    skip(request, form, pool, session, login_throttle, trusted_proxies),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_second_factor(
+    request: HttpRequest,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    trusted_proxies: web::Data<TrustedProxies>,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let user_id = session
+        .get_pending_user_id()
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+    let Some(user_id) = user_id else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let username = get_username(user_id, &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    let ip = trusted_proxies.client_ip(&request).map(|ip| ip.to_string());
+    // Wrong codes count against the same budget as wrong passwords
+    let blocked_for = login_throttle
+        .blocked_for(&username, ip.as_deref())
+        .await
+        .context("Failed to check the failed login attempts")
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if let Some(retry_after) = blocked_for {
+        session.remove_pending_user_id();
+        return Err(login_redirect(LoginError::TooManyAttempts { retry_after }));
+    }
+
+    let verified = verify_second_factor(user_id, form.code.expose_secret(), &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if verified {
+        login_throttle
+            .forget(&username)
+            .await
+            .context("Failed to reset the failed login attempts")
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+        session.renew();
+        start_session(&session, user_id, &pool)
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+        return Ok(see_other("/admin/dashboard"));
+    }
+
+    login_throttle
+        .record_failure(&username, ip.as_deref())
+        .await
+        .context("Failed to record a failed login attempt")
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    let attempts = session
+        .get_second_factor_attempts()
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?
+        + 1;
+    if attempts >= MAX_SECOND_FACTOR_ATTEMPTS {
+        tracing::warn!("Too many wrong second factor codes, the password is needed again");
+        session.remove_pending_user_id();
+        let e = LoginError::AuthError(anyhow::anyhow!("Too many wrong second factor codes."));
+        return Err(login_redirect(e));
+    }
+    session
+        .insert_second_factor_attempts(attempts)
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+    FlashMessage::error("Authentication failed").send();
+    let e = LoginError::AuthError(anyhow::anyhow!("Invalid second factor code."));
+    Err(InternalError::from_response(
+        e,
+        see_other("/login/two_factor"),
+    ))
+}
@@ -1,12 +1,18 @@
 mod admin;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
+mod password_reset;
 mod subscription_confirm;
 mod subscriptions;
+mod unsubscribe;
 pub use admin::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
+pub use password_reset::*;
 pub use subscription_confirm::*;
 pub use subscriptions::*;
+pub use unsubscribe::*;
@@ -0,0 +1,111 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie},
+    http::header::ContentType,
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::PasswordResetToken;
+use crate::utils::{e404, e500};
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+fn flash_html(flash_messages: &IncomingFlashMessages) -> String {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    msg_html
+}
+
+pub async fn password_reset_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let msg_html = flash_html(&flash_messages);
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Forgot your password?</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>We will email you a link to choose a new password.</p>
+                <form action="/password_reset" method="post">
+                    <label>Username
+                        <input type="text" placeholder="Enter Username" name="username">
+                    </label>
+                    <button type="submit">Send the link</button>
+                </form>
+                <p><a href="/login">&lt;- Back</a></p>
+            </body>
+            </html>"#,
+        ))
+}
+
+pub async fn new_password_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = PasswordResetToken::parse(parameters.0.token)
+        .map_err(|_| e404("This link is not valid or has expired."))?;
+    sqlx::query!(
+        r#"
+        SELECT user_id FROM password_reset_tokens
+        WHERE token_hash = $1 AND expires_at > now()
+        "#,
+        token.hash(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the password reset token")
+    .map_err(e500)?
+    .ok_or_else(|| e404("This link is not valid or has expired."))?;
+
+    let msg_html = flash_html(&flash_messages);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(Cookie::build("_flash", "").max_age(Duration::ZERO).finish())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Choose a new password</title>
+            </head>
+            <body>
+                {msg_html}
+                <form action="/password_reset/confirm" method="post">
+                    <input hidden type="text" name="token" value="{token}">
+                    <label>New password
+                        <input
+                            type="password"
+                            placeholder="Enter new password"
+                            name="new_password"
+                        >
+                    </label>
+                    <br>
+                    <label>Confirm new password
+                        <input
+                            type="password"
+                            placeholder="Type the new password again"
+                            name="new_password_check"
+                        >
+                    </label>
+                    <br>
+                    <button type="submit">Change password</button>
+                </form>
+            </body>
+            </html>"#,
+            token = token.as_ref(),
+        )))
+}
@@ -0,0 +1,196 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Executor, PgPool};
+use std::time::Duration;
+
+use crate::authentication::{
+    change_password, check_password_strength, invalidate_sessions, PasswordHashing,
+};
+use crate::domain::{PasswordResetToken, SubscriberEmail};
+use crate::email_client::{EmailSender, SendEmailError};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e404, e500, see_other};
+
+/// How long a reset link keeps working.
+const PASSWORD_RESET_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(serde::Deserialize)]
+pub struct ResetRequestFormData {
+    username: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewPasswordFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+/// Always answers the same way, so it cannot be used to find out which
+/// usernames exist.
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, pool, email_client, base_url),
+    fields(username = %form.username)
+)]
+pub async fn request_password_reset(
+    form: web::Form<ResetRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user = sqlx::query!(
+        r#"
+        SELECT user_id, email AS "email!"
+        FROM users
+        WHERE username = $1 AND email IS NOT NULL AND disabled_at IS NULL
+        "#,
+        form.username,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look for the user")
+    .map_err(e500)?;
+
+    if let Some(user) = user {
+        let token = PasswordResetToken::generate();
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")
+            .map_err(e500)?;
+        // Only the latest link works
+        let query = sqlx::query!(
+            "DELETE FROM password_reset_tokens WHERE user_id = $1",
+            user.user_id
+        );
+        transaction
+            .execute(query)
+            .await
+            .context("Failed to delete previous password reset tokens")
+            .map_err(e500)?;
+        let query = sqlx::query!(
+            r#"
+            INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+            VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+            "#,
+            token.hash(),
+            user.user_id,
+            PASSWORD_RESET_TTL.as_secs_f64(),
+        );
+        transaction
+            .execute(query)
+            .await
+            .context("Failed to store the password reset token")
+            .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to request a password reset")
+            .map_err(e500)?;
+
+        // In the background: how long the email takes must not give the user away
+        let email_client = email_client.into_inner();
+        let base_url = base_url.0.clone();
+        tokio::spawn(async move {
+            let result = match SubscriberEmail::parse(user.email) {
+                Ok(recipient) => {
+                    send_password_reset_email(email_client.as_ref(), &recipient, &base_url, &token)
+                        .await
+                        .map_err(anyhow::Error::from)
+                }
+                Err(e) => Err(anyhow::anyhow!(e)),
+            };
+            if let Err(e) = result {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a password reset email"
+                );
+            }
+        });
+    }
+
+    FlashMessage::info(
+        "If this account exists, a link to reset its password has been sent to its email address.",
+    )
+    .send();
+    Ok(see_other("/login"))
+}
+
+async fn send_password_reset_email(
+    email_client: &dyn EmailSender,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    token: &PasswordResetToken,
+) -> Result<(), SendEmailError> {
+    let reset_link = format!(
+        "{}/password_reset/confirm?token={}",
+        base_url,
+        token.as_ref()
+    );
+    let plain_body = format!(
+        "Someone asked to reset your password.\n\
+        Visit {} within the hour to choose a new one, or ignore this email.",
+        reset_link
+    );
+    let html_body = format!(
+        "Someone asked to reset your password.<br />\
+        Click <a href=\"{}\">here</a> within the hour to choose a new one, or ignore this email.",
+        reset_link
+    );
+    email_client
+        .send_email(recipient, "Reset your password", &html_body, &plain_body)
+        .await
+}
+
+#[tracing::instrument(name = "Reset a password", skip_all)]
+pub async fn reset_password(
+    form: web::Form<NewPasswordFormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let token = PasswordResetToken::parse(form.token)
+        .map_err(|_| e404("This link is not valid or has expired."))?;
+    let form_location = format!("/password_reset/confirm?token={}", token.as_ref());
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return Ok(see_other(&form_location));
+    }
+    if let Err(e) = check_password_strength(&form.new_password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&form_location));
+    }
+
+    // Deleting the token as we read it: it can only be used once
+    let user_id = sqlx::query!(
+        r#"
+        DELETE FROM password_reset_tokens t
+        USING users u
+        WHERE t.token_hash = $1
+            AND t.expires_at > now()
+            AND u.user_id = t.user_id
+            AND u.disabled_at IS NULL
+        RETURNING t.user_id
+        "#,
+        token.hash(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to use the password reset token")
+    .map_err(e500)?
+    .ok_or_else(|| e404("This link is not valid or has expired."))?
+    .user_id;
+    change_password(user_id, form.new_password, &hashing, &pool)
+        .await
+        .map_err(e500)?;
+    invalidate_sessions(user_id, &pool).await.map_err(e500)?;
+    FlashMessage::info("Your password has been reset, you can log in.").send();
+    Ok(see_other("/login"))
+}
@@ -1,10 +1,14 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::header::{Accept, ContentType, Header};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::domain::SubscriptionToken;
+use crate::domain::{StatusChange, SubscriptionStatus, SubscriptionToken};
+use crate::routes::change_subscription_status;
+use crate::utils::Problem;
 
 fn error_chain_fmt(
     e: &impl std::error::Error,
@@ -21,6 +25,8 @@ fn error_chain_fmt(
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
+    /// Left empty when missing, to be rejected like any other invalid token.
+    #[serde(default)]
     subscription_token: String,
 }
 
@@ -54,53 +60,160 @@ impl ResponseError for SubscriptionTokenError {
             SubscriptionTokenError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        match self {
+            SubscriptionTokenError::ValidationError(e)
+            | SubscriptionTokenError::AuthorizationError(e) => problem.detail(e).response(),
+            SubscriptionTokenError::UnexpectedError(_) => problem.response(),
+        }
+    }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(db_pool, parameters))]
+/// What became of a confirmation link the token was valid for.
+enum ConfirmationOutcome {
+    Confirmed,
+    AlreadyConfirmed,
+    Expired,
+    /// The subscriber unsubscribed since the link was sent.
+    NoLongerValid,
+}
+
+impl ConfirmationOutcome {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Confirmed | Self::AlreadyConfirmed => StatusCode::OK,
+            Self::Expired | Self::NoLongerValid => StatusCode::GONE,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "Your subscription is confirmed.",
+            Self::AlreadyConfirmed => "You have already confirmed your subscription.",
+            Self::Expired => {
+                "This confirmation link has expired. \
+                Subscribe again with the same email address to receive a new one."
+            }
+            Self::NoLongerValid => "This confirmation link is no longer valid.",
+        }
+    }
+
+    fn html_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .content_type(ContentType::html())
+            .body(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscription confirmation</title>
+</head>
+<body>
+    <p>{}</p>
+</body>
+</html>"#,
+                self.message()
+            ))
+    }
+
+    fn json_response(&self) -> HttpResponse {
+        match self {
+            Self::Confirmed | Self::AlreadyConfirmed => {
+                HttpResponse::Ok().json(serde_json::json!({
+                    "status": SubscriptionStatus::Confirmed.as_str(),
+                    "message": self.message(),
+                }))
+            }
+            Self::Expired | Self::NoLongerValid => Problem::new(self.status_code())
+                .detail(self.message())
+                .response(),
+        }
+    }
+}
+
+/// Whether the client would rather have JSON than a page, e.g. a frontend
+/// confirming subscriptions on its own pages.
+fn prefers_json(request: &HttpRequest) -> bool {
+    Accept::parse(request)
+        .ok()
+        .and_then(|accept| accept.ranked().into_iter().next())
+        .is_some_and(|preferred| preferred.essence_str() == "application/json")
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(request, db_pool, parameters)
+)]
 pub async fn confirm(
+    request: HttpRequest,
     parameters: web::Query<Parameters>,
     db_pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, SubscriptionTokenError> {
     let token = SubscriptionToken::parse(parameters.subscription_token.clone())?;
-    let id = get_subscriber_id_from_token(&db_pool, &token)
+    let subscriber = get_subscriber_from_token(&db_pool, &token)
         .await
         .context("Failed to select subscriber token with subscription id")?;
 
-    match id {
-        None => Err(SubscriptionTokenError::AuthorizationError(
-            "Invalid subscription token".into(),
-        )),
-        Some(subscriber_id) => {
-            confirm_subscriber(&db_pool, subscriber_id)
+    let outcome = match subscriber {
+        None => {
+            return Err(SubscriptionTokenError::AuthorizationError(
+                "Invalid subscription token".into(),
+            ))
+        }
+        // Old links of confirmed subscribers have nothing left to do, expired or not
+        Some((_, _, SubscriptionStatus::Confirmed)) => ConfirmationOutcome::AlreadyConfirmed,
+        Some((_, expires_at, _)) if expires_at <= Utc::now() => ConfirmationOutcome::Expired,
+        Some((subscriber_id, _, _)) => {
+            let mut transaction = db_pool
+                .begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool")?;
+            let change = change_subscription_status(
+                &mut transaction,
+                subscriber_id,
+                SubscriptionStatus::Confirmed,
+            )
+            .await
+            .context("Failed to confirm subscription")?;
+            transaction
+                .commit()
                 .await
-                .context("Failed to confirm subscription")?;
-            Ok(HttpResponse::Ok().finish())
+                .context("Failed to commit SQL transaction to confirm a subscriber")?;
+            match change {
+                StatusChange::Applied => ConfirmationOutcome::Confirmed,
+                StatusChange::AlreadyApplied => ConfirmationOutcome::AlreadyConfirmed,
+                StatusChange::NotAllowed => ConfirmationOutcome::NoLongerValid,
+            }
         }
+    };
+    if prefers_json(&request) {
+        Ok(outcome.json_response())
+    } else {
+        Ok(outcome.html_response())
     }
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id,
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
-}
-
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
+/// The subscriber the token was sent to, when it expires and the current
+/// status of the subscriber.
+#[tracing::instrument(name = "Get subscriber from token", skip(subscription_token, pool))]
+pub async fn get_subscriber_from_token(
     pool: &PgPool,
     subscription_token: &SubscriptionToken,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, DateTime<Utc>, SubscriptionStatus)>, anyhow::Error> {
     let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens \
-        WHERE subscription_token = $1",
+        "SELECT t.subscriber_id, t.expires_at, s.status FROM subscription_tokens t \
+        JOIN subscriptions s ON s.id = t.subscriber_id \
+        WHERE t.subscription_token = $1",
         subscription_token.as_ref(),
     )
     .fetch_optional(pool)
     .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    result
+        .map(|r| {
+            let status = SubscriptionStatus::parse(&r.status).map_err(anyhow::Error::msg)?;
+            Ok((r.subscriber_id, r.expires_at, status))
+        })
+        .transpose()
 }
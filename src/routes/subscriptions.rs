@@ -1,31 +1,39 @@
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionToken},
-    email_client::EmailClient,
+    client_ip::TrustedProxies,
+    configuration::SubscriptionSettings,
+    domain::{
+        NewSubscriber, StatusChange, SubscriberEmail, SubscriberName, SubscriptionStatus,
+        SubscriptionToken, UnsubscribeToken,
+    },
+    email_client::{EmailSender, SendEmailError},
+    request_limiter::RequestLimiter,
     startup::ApplicationBaseUrl,
-    utils::error_chain_fmt,
+    utils::{error_chain_fmt, Problem},
 };
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
 use actix_web::ResponseError;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use anyhow::Context;
 use chrono::Utc;
 use reqwest::StatusCode;
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
+    #[error("{reason}")]
+    ValidationError { field: &'static str, reason: String },
     #[error("{0}")]
-    ValidationError(String),
+    MalformedRequest(String),
+    #[error("Subscription requests must be sent as a form or as JSON")]
+    UnsupportedMediaType,
+    #[error("Too many subscription requests")]
+    RateLimited { retry_after: Duration },
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
 
-impl From<String> for SubscribeError {
-    fn from(e: String) -> Self {
-        Self::ValidationError(e)
-    }
-}
-
 impl std::fmt::Debug for SubscribeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -35,44 +43,145 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError { .. } | SubscribeError::MalformedRequest(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SubscribeError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            SubscribeError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = Problem::new(self.status_code());
+        let mut response = match self {
+            SubscribeError::ValidationError { field, reason } => problem
+                .detail("The subscription request is invalid")
+                .invalid_param(field, reason)
+                .response(),
+            SubscribeError::MalformedRequest(_)
+            | SubscribeError::UnsupportedMediaType
+            | SubscribeError::RateLimited { .. } => problem.detail(self.to_string()).response(),
+            SubscribeError::UnexpectedError(_) => problem.response(),
+        };
+        if let SubscribeError::RateLimited { retry_after } = self {
+            // Rounded up: retrying on the dot of the window still works
+            let seconds = retry_after.as_millis().div_ceil(1000);
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds as u64));
+        }
+        response
+    }
 }
 
+/// The body of `POST /subscriptions`, from the form on our home page or as
+/// JSON from other frontends. Missing fields are left empty, to be reported
+/// along with the invalid ones.
 #[derive(serde::Deserialize)]
 pub struct FormData {
+    #[serde(default)]
     email: String,
+    #[serde(default)]
     name: String,
+    /// A honeypot: the field is hidden from people, bots fill it in.
+    #[serde(default)]
+    website: String,
+}
+
+impl FormData {
+    fn parse(request: &HttpRequest, body: &[u8]) -> Result<Self, SubscribeError> {
+        let mime_type = request
+            .mime_type()
+            .map_err(|_| SubscribeError::UnsupportedMediaType)?;
+        match mime_type.as_ref().map(|m| m.essence_str()) {
+            Some("application/json") => serde_json::from_slice(body)
+                .map_err(|e| SubscribeError::MalformedRequest(e.to_string())),
+            Some("application/x-www-form-urlencoded") => serde_urlencoded::from_bytes(body)
+                .map_err(|e| SubscribeError::MalformedRequest(e.to_string())),
+            _ => Err(SubscribeError::UnsupportedMediaType),
+        }
+    }
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = SubscribeError;
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
+        let name = SubscriberName::parse(value.name).map_err(|reason| {
+            SubscribeError::ValidationError {
+                field: "name",
+                reason,
+            }
+        })?;
+        let email = SubscriberEmail::parse(value.email).map_err(|reason| {
+            SubscribeError::ValidationError {
+                field: "email",
+                reason,
+            }
+        })?;
         Ok(Self { email, name })
     }
 }
 
+// Every argument is an extractor: grouping them would only hide what the
+// handler depends on
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, db_pool, email_client),
+    skip(
+        request,
+        body,
+        db_pool,
+        email_client,
+        subscription_settings,
+        request_limiter,
+        trusted_proxies
+    ),
     fields(
-    subscriber_email = %form.email,
-    subscriber_name = %form.name,
+    subscriber_email = tracing::field::Empty,
+    subscriber_name = tracing::field::Empty,
     base_url
     )
 )]
 pub async fn subscribe(
+    request: HttpRequest,
+    body: web::Bytes,
     db_pool: web::Data<PgPool>,
-    form: web::Form<FormData>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailSender>,
     base_url: web::Data<ApplicationBaseUrl>,
+    subscription_settings: web::Data<SubscriptionSettings>,
+    request_limiter: web::Data<RequestLimiter>,
+    trusted_proxies: web::Data<TrustedProxies>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into()?;
-    let existing_sub = select_subscriber(&db_pool, &new_subscriber)
+    let form = FormData::parse(&request, &body)?;
+    tracing::Span::current()
+        .record("subscriber_email", tracing::field::display(&form.email))
+        .record("subscriber_name", tracing::field::display(&form.name));
+    if !form.website.is_empty() {
+        // Looking like a success gives bots nothing to adapt to
+        tracing::info!("Dropping a subscription request that filled in the honeypot");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let limits = request_limiter.settings();
+    if let Some(ip) = trusted_proxies.client_ip(&request) {
+        check_rate_limit(
+            &request_limiter,
+            "ip",
+            &ip.to_string(),
+            limits.max_requests_per_ip,
+        )
+        .await?;
+    }
+    let token_ttl = subscription_settings.confirmation_token_ttl();
+    let new_subscriber: NewSubscriber = form.try_into()?;
+    check_rate_limit(
+        &request_limiter,
+        "email",
+        &new_subscriber.email.as_ref().to_lowercase(),
+        limits.max_requests_per_email,
+    )
+    .await?;
+    let existing_sub = select_subscriber(db_pool.get_ref(), &new_subscriber)
         .await
         .context("Failed selecting existing_subscriber from the database")?;
 
@@ -81,14 +190,30 @@ pub async fn subscribe(
             .begin()
             .await
             .context("Failed to acquire a Postgres connection from the pool")?;
-        let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-            .await
-            .context("Failed inserting subscriber into the database")?;
+        let subscriber_id = insert_subscriber(
+            &mut transaction,
+            &new_subscriber,
+            SubscriptionStatus::PendingConfirmation,
+        )
+        .await
+        .context("Failed inserting subscriber into the database")?;
 
-        let subscription_token = SubscriptionToken::generate_subscription_token();
-        store_token(&mut transaction, subscriber_id, &subscription_token)
-            .await
-            .context("Failed to store confirmation token into the database")?;
+        let subscription_token = SubscriptionToken::generate();
+        store_token(
+            &mut transaction,
+            subscriber_id,
+            &subscription_token,
+            token_ttl,
+        )
+        .await
+        .context("Failed to store confirmation token into the database")?;
+        store_unsubscribe_token(
+            &mut transaction,
+            subscriber_id,
+            &UnsubscribeToken::generate(),
+        )
+        .await
+        .context("Failed to store unsubscribe token into the database")?;
 
         transaction
             .commit()
@@ -96,7 +221,7 @@ pub async fn subscribe(
             .context("Failed to commit SQL transaction to store a new subscriber")?;
 
         send_confirmation_email(
-            &email_client,
+            email_client.as_ref(),
             new_subscriber,
             &base_url.0,
             &subscription_token,
@@ -104,12 +229,39 @@ pub async fn subscribe(
         .await
         .context("Failed to send confirmation email")?;
     } else {
-        let subscription_token = select_subscription_token(&db_pool, existing_sub.unwrap())
+        // A fresh link every time: whatever was sent before stops working
+        let subscriber_id = existing_sub.unwrap();
+        let mut transaction = db_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        // People who unsubscribed have to confirm again. Confirmed subscribers
+        // stay confirmed: the link we send them says so.
+        change_subscription_status(
+            &mut transaction,
+            subscriber_id,
+            SubscriptionStatus::PendingConfirmation,
+        )
+        .await
+        .context("Failed to move the subscriber back to pending confirmation")?;
+        revoke_tokens(&mut transaction, subscriber_id)
+            .await
+            .context("Failed to revoke the previous confirmation tokens")?;
+        let subscription_token = SubscriptionToken::generate();
+        store_token(
+            &mut transaction,
+            subscriber_id,
+            &subscription_token,
+            token_ttl,
+        )
+        .await
+        .context("Failed to store confirmation token into the database")?;
+        transaction
+            .commit()
             .await
-            .context("Failed to select subscription token from the database")?;
-        let subscription_token = SubscriptionToken::parse(subscription_token).unwrap();
+            .context("Failed to commit SQL transaction to rotate a confirmation token")?;
         send_confirmation_email(
-            &email_client,
+            email_client.as_ref(),
             new_subscriber,
             &base_url.0,
             &subscription_token,
@@ -121,16 +273,32 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+async fn check_rate_limit(
+    request_limiter: &RequestLimiter,
+    scope: &str,
+    subject: &str,
+    max_requests: u64,
+) -> Result<(), SubscribeError> {
+    let retry_after = request_limiter
+        .hit(scope, subject, max_requests)
+        .await
+        .context("Failed to check the subscription rate limits")?;
+    match retry_after {
+        Some(retry_after) => Err(SubscribeError::RateLimited { retry_after }),
+        None => Ok(()),
+    }
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
     skip(email_client, new_subscriber, base_url, subscription_token)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &SubscriptionToken,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), SendEmailError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url,
@@ -152,19 +320,22 @@ pub async fn send_confirmation_email(
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(new_subscriber, db_pool)
+    skip(new_subscriber, executor)
 )]
-pub async fn select_subscriber(
-    db_pool: &PgPool,
+pub async fn select_subscriber<'c, E>(
+    executor: E,
     new_subscriber: &NewSubscriber,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
     let result = sqlx::query!(
         r#"
-            SELECT id FROM Subscriptions WHERE email = $1
+            SELECT id FROM Subscriptions WHERE lower(email) = lower($1)
         "#,
         new_subscriber.email.as_ref(),
     )
-    .fetch_optional(db_pool)
+    .fetch_optional(executor)
     .await?;
     Ok(result.map(|r| r.id))
 }
@@ -176,17 +347,19 @@ pub async fn select_subscriber(
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
+    status: SubscriptionStatus,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
     let query = sqlx::query!(
         r#"
     INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    VALUES ($1, $2, $3, $4, $5)
     "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        status.as_str(),
     );
     transaction.execute(query).await?;
 
@@ -201,29 +374,78 @@ pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     subscription_token: &SubscriptionToken,
+    ttl: std::time::Duration,
 ) -> Result<(), sqlx::Error> {
     let query = sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (
+            subscription_token, subscriber_id, created_at, expires_at
+        )
+        VALUES ($1, $2, now(), now() + make_interval(secs => $3))"#,
         subscription_token.as_ref(),
+        subscriber_id,
+        ttl.as_secs_f64()
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Revoking confirmation tokens", skip(transaction))]
+pub async fn revoke_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
         subscriber_id
     );
     transaction.execute(query).await?;
     Ok(())
 }
 
-#[tracing::instrument(name = "Saving new subscriber details in the database", skip(db_pool))]
-pub async fn select_subscription_token(
-    db_pool: &PgPool,
+/// Move a subscriber to `next` if `SubscriptionStatus::transition_to` allows it.
+/// The row stays locked until `transaction` ends, so concurrent changes queue up
+/// behind each other instead of overwriting one another.
+#[tracing::instrument(name = "Changing the status of a subscriber", skip(transaction))]
+pub async fn change_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-) -> Result<String, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscription_token FROM subscription_tokens
-        WHERE subscriber_id = $1"#,
-        subscriber_id,
+    next: SubscriptionStatus,
+) -> Result<StatusChange, anyhow::Error> {
+    let current = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
     )
-    .fetch_one(db_pool)
-    .await?;
+    .fetch_one(&mut **transaction)
+    .await?
+    .status;
+    let current = SubscriptionStatus::parse(&current).map_err(anyhow::Error::msg)?;
+    let change = current.transition_to(next);
+    if change == StatusChange::Applied {
+        let query = sqlx::query!(
+            r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+            subscriber_id,
+            next.as_str(),
+        );
+        transaction.execute(query).await?;
+    }
+    Ok(change)
+}
 
-    Ok(result.subscription_token)
+#[tracing::instrument(
+    name = "Saving unsubscribe token in the database",
+    skip(unsubscribe_token, transaction)
+)]
+pub async fn store_unsubscribe_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"INSERT INTO unsubscribe_tokens (unsubscribe_token, subscriber_id)
+        VALUES ($1, $2)"#,
+        unsubscribe_token.as_ref(),
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
 }
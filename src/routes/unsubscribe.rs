@@ -0,0 +1,137 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{SubscriptionStatus, UnsubscribeToken};
+use crate::routes::{change_subscription_status, SubscriptionTokenError};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    unsubscribe_token: String,
+}
+
+/// Mail scanners follow links: the GET only asks for confirmation, the POST
+/// (from this page or a mail client's one-click button) does the unsubscribing.
+#[tracing::instrument(name = "Ask a subscriber to confirm unsubscribing", skip_all)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriptionTokenError> {
+    let token = UnsubscribeToken::parse(parameters.0.unsubscribe_token)?;
+    let (_, subscriber_email) = get_subscriber_from_token(&db_pool, &token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the unsubscribe token")?
+        .ok_or_else(|| {
+            SubscriptionTokenError::AuthorizationError("Invalid unsubscribe token".into())
+        })?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter at {}?</p>
+    <form action="/subscriptions/unsubscribe?unsubscribe_token={}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            htmlescape::encode_minimal(&subscriber_email),
+            token.as_ref(),
+        )))
+}
+
+/// The token travels in the query string: one-click requests (RFC 8058) POST
+/// to the URL of the `List-Unsubscribe` header with a fixed body we can ignore.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip_all)]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriptionTokenError> {
+    let token = UnsubscribeToken::parse(parameters.0.unsubscribe_token)?;
+    let (subscriber_id, subscriber_email) = get_subscriber_from_token(&db_pool, &token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the unsubscribe token")?
+        .ok_or_else(|| {
+            SubscriptionTokenError::AuthorizationError("Invalid unsubscribe token".into())
+        })?;
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Unsubscribing twice is fine: there is nothing left to cancel either
+    change_subscription_status(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+    .context("Failed to mark the subscriber as unsubscribed")?;
+    cancel_pending_deliveries_to(&mut transaction, &subscriber_email)
+        .await
+        .context("Failed to cancel the deliveries still pending for the subscriber")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>You have been unsubscribed.</p>
+</body>
+</html>"#,
+    ))
+}
+
+#[tracing::instrument(name = "Get subscriber from unsubscribe token", skip_all)]
+async fn get_subscriber_from_token(
+    pool: &PgPool,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT s.id, s.email
+        FROM unsubscribe_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.unsubscribe_token = $1
+        "#,
+        unsubscribe_token.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.map(|r| (r.id, r.email)))
+}
+
+/// Issues that are being delivered right now must not reach the subscriber either.
+/// Returns how many issues had a delivery cancelled.
+#[tracing::instrument(skip(transaction))]
+pub async fn cancel_pending_deliveries_to(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_email: &str,
+) -> Result<u64, sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        WITH cancelled AS (
+            DELETE FROM issue_delivery_queue
+            WHERE lower(subscriber_email) = lower($1)
+            RETURNING newsletter_issue_id
+        )
+        UPDATE newsletter_issues i
+        SET n_cancelled = n_cancelled + 1
+        FROM cancelled c
+        WHERE i.newsletter_issue_id = c.newsletter_issue_id
+        "#,
+        subscriber_email,
+    );
+    Ok(transaction.execute(query).await?.rows_affected())
+}
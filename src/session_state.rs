@@ -6,6 +6,10 @@ use uuid::Uuid;
 pub struct TypedSession(Session);
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_GENERATION_KEY: &'static str = "session_generation";
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const SECOND_FACTOR_ATTEMPTS_KEY: &'static str = "second_factor_attempts";
+    const TOTP_ENROLMENT_SECRET_KEY: &'static str = "totp_enrolment_secret";
     pub fn renew(&self) {
         self.0.renew();
     }
@@ -17,6 +21,52 @@ impl TypedSession {
     pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
         self.0.get(Self::USER_ID_KEY)
     }
+    pub fn insert_session_generation(&self, generation: i32) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_GENERATION_KEY, generation)
+    }
+
+    /// Sessions opened before generations existed belong to the first one.
+    pub fn get_session_generation(&self) -> Result<i32, SessionGetError> {
+        Ok(self.0.get(Self::SESSION_GENERATION_KEY)?.unwrap_or(0))
+    }
+
+    /// The password of `user_id` has been verified, but not their second
+    /// factor yet: they are not logged in.
+    pub fn insert_pending_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+
+    pub fn get_pending_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+
+    pub fn remove_pending_user_id(&self) {
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+        self.0.remove(Self::SECOND_FACTOR_ATTEMPTS_KEY);
+    }
+
+    pub fn insert_second_factor_attempts(&self, attempts: u32) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SECOND_FACTOR_ATTEMPTS_KEY, attempts)
+    }
+
+    pub fn get_second_factor_attempts(&self) -> Result<u32, SessionGetError> {
+        Ok(self.0.get(Self::SECOND_FACTOR_ATTEMPTS_KEY)?.unwrap_or(0))
+    }
+
+    /// The secret shown to a user setting up their authenticator, until they
+    /// confirm it with a first code.
+    pub fn insert_totp_enrolment_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::TOTP_ENROLMENT_SECRET_KEY, secret)
+    }
+
+    pub fn get_totp_enrolment_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::TOTP_ENROLMENT_SECRET_KEY)
+    }
+
+    pub fn remove_totp_enrolment_secret(&self) {
+        self.0.remove(Self::TOTP_ENROLMENT_SECRET_KEY);
+    }
+
     pub fn log_out(&self) {
         self.0.purge()
     }
@@ -0,0 +1,30 @@
+use tokio_util::sync::CancellationToken;
+
+/// Returns a token that is cancelled as soon as the process is asked to terminate,
+/// either with SIGTERM (e.g. during a redeploy) or SIGINT (Ctrl+C).
+/// Long-running tasks are expected to watch it and wind down gracefully.
+pub fn shutdown_signal() -> CancellationToken {
+    let token = CancellationToken::new();
+    let shutdown = token.clone();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        tracing::info!("Shutdown signal received, stopping gracefully");
+        shutdown.cancel();
+    });
+    token
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
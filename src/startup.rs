@@ -10,18 +10,38 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 
-use crate::authentication::reject_anonymous_users;
+use crate::authentication::{
+    reject_anonymous_users, require_editor, require_owner, PasswordHashing,
+};
+use crate::client_ip::TrustedProxies;
 use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
-use crate::routes::get::newsletter_form;
+use crate::email_client::EmailSender;
+use crate::login_throttle::LoginThrottle;
+use crate::request_limiter::RequestLimiter;
+use crate::routes::get::{newsletter_form, newsletter_issue};
+use crate::routes::{
+    accept_invitation, admin_dashboard, cancel_newsletter_issue, change_password,
+    change_password_form, confirm, confirm_subscriber, delete_subscriber, delete_user,
+    disable_user, enable_user, erase_subject, export_newsletter_issue_deliveries,
+    export_subscribers, failed_deliveries, health_check, import_subscribers,
+    import_subscribers_form, invitation_form, invite_user, log_out, pause_newsletter_issue,
+    publish_newsletter, requeue_all_failed_deliveries, requeue_failed_delivery,
+    reschedule_newsletter_issue, resume_newsletter_issue, subject_data, subscribe,
+    subscriber_details, subscribers, unsubscribe, unsubscribe_form, unsubscribe_subscriber, users,
+};
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, log_out,
-    publish_newsletter, subscribe,
+    disable_two_factor, enable_two_factor, login_second_factor, regenerate_recovery_codes,
+    reset_two_factor, second_factor_form, two_factor_settings,
 };
 
-use crate::routes::{home, login, login_form};
+use crate::routes::{
+    home, login, login_form, new_password_form, password_reset_form, request_password_reset,
+    reset_password,
+};
 use actix_web_flash_messages::FlashMessagesFramework;
 
 pub struct Application {
@@ -34,7 +54,7 @@ impl Application {
     // `Application`.
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let email_client = configuration.email_client.client();
+        let email_client = configuration.email_client.clone().client();
 
         let address = format!(
             "{}:{}",
@@ -42,15 +62,7 @@ impl Application {
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
-        let server = run(
-            listener,
-            connection_pool,
-            email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
-            configuration.redis_uri,
-        )
-        .await?;
+        let server = run(listener, connection_pool, email_client, configuration).await?;
         // We "save" the bound port in one of `Application`'s fields
         Ok(Self { port, server })
     }
@@ -60,7 +72,17 @@ impl Application {
     }
     // A more expressive name that makes it clear that
     // this function only returns when the application is stopped.
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+    // Once `shutdown` is cancelled the server stops accepting connections and
+    // gives in-flight requests up to the configured timeout to complete.
+    pub async fn run_until_stopped(
+        self,
+        shutdown: CancellationToken,
+    ) -> Result<(), std::io::Error> {
+        let server_handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            server_handle.stop(true).await;
+        });
         self.server.await
     }
 }
@@ -74,14 +96,30 @@ pub struct ApplicationBaseUrl(pub String);
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
+    email_client: Arc<dyn EmailSender>,
+    configuration: Settings,
 ) -> Result<Server, anyhow::Error> {
+    let shutdown_timeout = configuration.application.shutdown_timeout();
+    let hmac_secret = configuration.application.hmac_secret;
+    let redis_uri = configuration.redis_uri;
     let db_pool = Data::new(db_pool);
-    let email_client = Data::new(email_client);
-    let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let email_client: Data<dyn EmailSender> = Data::from(email_client);
+    let trusted_proxies = Data::new(TrustedProxies(
+        configuration.application.trusted_proxies.clone(),
+    ));
+    let base_url = Data::new(ApplicationBaseUrl(configuration.application.base_url));
+    let request_limiter = Data::new(
+        RequestLimiter::new(
+            redis_uri.expose_secret(),
+            configuration.subscriptions.rate_limit.clone(),
+        )
+        .await?,
+    );
+    let login_throttle = Data::new(
+        LoginThrottle::new(redis_uri.expose_secret(), configuration.login_throttle).await?,
+    );
+    let password_hashing = Data::new(PasswordHashing::new(&configuration.password_hashing)?);
+    let subscription_settings = Data::new(configuration.subscriptions);
     let message_store =
         CookieMessageStore::builder(Key::from(hmac_secret.expose_secret().as_bytes())).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
@@ -99,24 +137,163 @@ pub async fn run(
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/two_factor", web::get().to(second_factor_form))
+            .route("/login/two_factor", web::post().to(login_second_factor))
+            .route("/password_reset", web::get().to(password_reset_form))
+            .route("/password_reset", web::post().to(request_password_reset))
+            .route("/password_reset/confirm", web::get().to(new_password_form))
+            .route("/password_reset/confirm", web::post().to(reset_password))
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/invitations/{invitation_token}",
+                web::get().to(invitation_form),
+            )
+            .route(
+                "/invitations/{invitation_token}",
+                web::post().to(accept_invitation),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/newsletters", web::get().to(newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route(
+                        "/newsletters",
+                        web::post()
+                            .to(publish_newsletter)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/export",
+                        web::get().to(export_newsletter_issue_deliveries),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/reschedule",
+                        web::post()
+                            .to(reschedule_newsletter_issue)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/pause",
+                        web::post()
+                            .to(pause_newsletter_issue)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/resume",
+                        web::post()
+                            .to(resume_newsletter_issue)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/cancel",
+                        web::post()
+                            .to(cancel_newsletter_issue)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(users))
+                            .route("/invite", web::post().to(invite_user))
+                            .route("/{user_id}/disable", web::post().to(disable_user))
+                            .route("/{user_id}/enable", web::post().to(enable_user))
+                            .route("/{user_id}/delete", web::post().to(delete_user))
+                            .route(
+                                "/{user_id}/reset_two_factor",
+                                web::post().to(reset_two_factor),
+                            ),
+                    )
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out)),
+                    .route("/two_factor", web::get().to(two_factor_settings))
+                    .route("/two_factor", web::post().to(enable_two_factor))
+                    .route("/two_factor/disable", web::post().to(disable_two_factor))
+                    .route(
+                        "/two_factor/recovery_codes",
+                        web::post().to(regenerate_recovery_codes),
+                    )
+                    .route("/logout", web::post().to(log_out))
+                    .route("/deliveries/failed", web::get().to(failed_deliveries))
+                    .route(
+                        "/deliveries/failed/requeue",
+                        web::post()
+                            .to(requeue_failed_delivery)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/deliveries/failed/requeue_all",
+                        web::post()
+                            .to(requeue_all_failed_deliveries)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route("/subscribers", web::get().to(subscribers))
+                    // Before `{subscriber_id}`, which would match it too
+                    .route(
+                        "/subscribers/import",
+                        web::get()
+                            .to(import_subscribers_form)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/subscribers/import",
+                        web::post()
+                            .to(import_subscribers)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route("/subscribers/data", web::get().to(subject_data))
+                    .route(
+                        "/subscribers/erase",
+                        web::post().to(erase_subject).wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post()
+                            .to(confirm_subscriber)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post()
+                            .to(unsubscribe_subscriber)
+                            .wrap(from_fn(require_editor)),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post()
+                            .to(delete_subscriber)
+                            .wrap(from_fn(require_editor)),
+                    ),
             )
             .app_data(base_url.clone())
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(subscription_settings.clone())
+            .app_data(request_limiter.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(login_throttle.clone())
+            .app_data(password_hashing.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
     })
+    // Shutdown is driven by `Application::run_until_stopped`
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs())
     .listen(listener)?
     .run();
 
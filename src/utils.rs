@@ -1,4 +1,5 @@
-use actix_web::http::header::LOCATION;
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
 // Return an opaque 500 while preserving the error root's cause for logging.
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -15,6 +16,20 @@ where
     actix_web::error::ErrorBadRequest(e)
 }
 
+pub fn e403<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorForbidden(e)
+}
+
+pub fn e404<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorNotFound(e)
+}
+
 pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
@@ -33,3 +48,55 @@ pub fn error_chain_fmt(
     }
     Ok(())
 }
+
+/// A "problem details" body (RFC 7807), telling API clients what went wrong in
+/// a shape they can act on.
+#[derive(serde::Serialize, Debug)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(rename = "invalid-params", skip_serializing_if = "Vec::is_empty")]
+    invalid_params: Vec<InvalidParam>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct InvalidParam {
+    name: &'static str,
+    reason: String,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            // No documentation behind our problems: the title says it all
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Unknown Error"),
+            status: status.as_u16(),
+            detail: None,
+            invalid_params: vec![],
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn invalid_param(mut self, name: &'static str, reason: impl Into<String>) -> Self {
+        self.invalid_params.push(InvalidParam {
+            name,
+            reason: reason.into(),
+        });
+        self
+    }
+
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::build(StatusCode::from_u16(self.status).unwrap())
+            .content_type(ContentType("application/problem+json".parse().unwrap()))
+            .json(self)
+    }
+}
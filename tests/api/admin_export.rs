@@ -0,0 +1,151 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, insert_subscriber, spawn_app,
+    PostmarkBatchResponse,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_subscribers_export("csv").await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(
+        &app,
+        "octavia@example.com",
+        "Le Guin, Octavia",
+        "unsubscribed",
+    )
+    .await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_subscribers_export("csv").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .contains("subscribers.csv"));
+    let body = response.text().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "email,name,status,subscribed_at");
+    assert!(lines[1].starts_with("ursula@example.com,Ursula,confirmed,"));
+    assert!(lines[2].starts_with(r#"octavia@example.com,"Le Guin, Octavia",unsubscribed,"#));
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(
+        &app,
+        "octavia@example.com",
+        "Octavia",
+        "pending_confirmation",
+    )
+    .await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_subscribers_export("json").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "application/json");
+    let subscribers: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(subscribers.len(), 2);
+    assert_eq!(subscribers[0]["email"], "ursula@example.com");
+    assert_eq!(subscribers[1]["status"], "pending_confirmation");
+    assert!(subscribers[1]["subscribed_at"].is_string());
+}
+
+#[tokio::test]
+async fn an_empty_export_is_still_valid_json() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_subscribers_export("json").await;
+    // Assert
+    let subscribers: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn an_unknown_export_format_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_subscribers_export("xml").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn exporting_an_unknown_issue_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_newsletter_issue_export(Uuid::new_v4(), "csv").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_issue_export_lists_each_recipient_with_their_delivery_outcome() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::rejecting_first(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = app.publish_newsletter().await;
+
+    // Act - Part 1 - Before delivery
+    let response = app.get_newsletter_issue_export(issue_id, "json").await;
+
+    // Assert - Part 1 - Everyone is pending
+    let deliveries: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(deliveries.len(), 2);
+    assert!(deliveries.iter().all(|d| d["outcome"] == "pending"));
+
+    // Act - Part 2 - After delivery
+    app.dispatch_all_pending_emails().await;
+    let response = app.get_newsletter_issue_export(issue_id, "json").await;
+
+    // Assert - Part 2 - One was sent, the other one failed
+    let deliveries: Vec<serde_json::Value> = response.json().await.unwrap();
+    let mut outcomes: Vec<_> = deliveries
+        .iter()
+        .map(|d| d["outcome"].as_str().unwrap())
+        .collect();
+    outcomes.sort();
+    assert_eq!(outcomes, ["failed", "sent"]);
+    let failed = deliveries
+        .iter()
+        .find(|d| d["outcome"] == "failed")
+        .unwrap();
+    assert!(failed["error"].is_string());
+}
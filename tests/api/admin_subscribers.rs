@@ -0,0 +1,234 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber,
+    insert_subscriber, spawn_app, PostmarkBatchResponse, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn subscriber_status(app: &TestApp, subscriber_id: Uuid) -> Option<String> {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_subscribers("").await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_delete_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "a@example.com", "A", "confirmed").await;
+    // Act
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(subscriber_status(&app, subscriber_id).await.is_some());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia", "confirmed").await;
+    insert_subscriber(&app, "ursa@example.com", "Ursa", "unsubscribed").await;
+    app.test_user.login(&app).await;
+    // Act - search
+    let html_page = app.get_subscribers_html("search=URS").await;
+    // Assert
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("ursa@example.com"));
+    assert!(!html_page.contains("octavia@example.com"));
+    // Act - search and filter
+    let html_page = app
+        .get_subscribers_html("search=urs&status=unsubscribed")
+        .await;
+    // Assert
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("ursa@example.com"));
+}
+
+#[tokio::test]
+async fn search_terms_are_not_patterns() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    app.test_user.login(&app).await;
+    // Act
+    let html_page = app.get_subscribers_html("search=%25").await;
+    // Assert
+    assert!(!html_page.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..60 {
+        let email = format!("subscriber{:02}@example.com", i);
+        insert_subscriber(&app, &email, "Subscriber", "confirmed").await;
+    }
+    app.test_user.login(&app).await;
+    // Act
+    let first_page = app.get_subscribers_html("").await;
+    let second_page = app.get_subscribers_html("page=2").await;
+    // Assert
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(second_page.contains("Page 2 of 2"));
+    let n_listed = |page: &str| page.matches("@example.com</a>").count();
+    assert_eq!(n_listed(&first_page), 50);
+    assert_eq!(n_listed(&second_page), 10);
+}
+
+#[tokio::test]
+async fn an_invalid_status_filter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_subscribers("status=deleted").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscriber_details_include_the_delivery_history() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let html_page = app.get_subscriber_details_html(subscriber.id).await;
+    // Assert
+    assert!(html_page.contains(&subscriber.email));
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains("<td>sent</td>"));
+}
+
+#[tokio::test]
+async fn admins_can_confirm_a_pending_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.post_subscriber_action(subscriber_id, "confirm").await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("The subscriber has been confirmed."));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await.unwrap(),
+        "confirmed"
+    );
+}
+
+#[tokio::test]
+async fn admins_cannot_confirm_someone_who_unsubscribed() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "a@example.com", "A", "unsubscribed").await;
+    app.test_user.login(&app).await;
+    // Act
+    app.post_subscriber_action(subscriber_id, "confirm").await;
+    // Assert
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("People who unsubscribed cannot be confirmed."));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await.unwrap(),
+        "unsubscribed"
+    );
+}
+
+#[tokio::test]
+async fn admins_can_unsubscribe_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "a@example.com", "A", "confirmed").await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app
+        .post_subscriber_action(subscriber_id, "unsubscribe")
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await.unwrap(),
+        "unsubscribed"
+    );
+}
+
+#[tokio::test]
+async fn admins_can_delete_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert!(subscriber_status(&app, subscriber_id).await.is_none());
+    let n_tokens = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn acting_on_an_unknown_subscriber_redirects_to_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.post_subscriber_action(Uuid::new_v4(), "delete").await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("There is no such subscriber."));
+}
@@ -0,0 +1,193 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, PostmarkBatchResponse, TestApp,
+};
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+/// How many rows of every table still mention `email` or its subscriber.
+async fn rows_about(app: &TestApp, email: &str) -> i64 {
+    sqlx::query!(
+        r#"
+        SELECT (
+            (SELECT count(*) FROM subscriptions WHERE email = $1)
+            + (SELECT count(*) FROM subscription_tokens t
+                JOIN subscriptions s ON s.id = t.subscriber_id WHERE s.email = $1)
+            + (SELECT count(*) FROM issue_delivery_queue WHERE subscriber_email = $1)
+            + (SELECT count(*) FROM sent_deliveries WHERE subscriber_email = $1)
+            + (SELECT count(*) FROM failed_deliveries WHERE subscriber_email = $1)
+        ) AS "count!"
+        "#,
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_or_erase_subject_data() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let data_response = app.get_subject_data("ursula@example.com").await;
+    let erase_response = app.post_erase_subject("ursula@example.com").await;
+    // Assert
+    assert_is_redirect_to(&data_response, "/login");
+    assert_is_redirect_to(&erase_response, "/login");
+}
+
+#[tokio::test]
+async fn the_subject_data_covers_their_subscription_and_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.publish_newsletter().await;
+    app.dispatch_all_pending_emails().await;
+    app.publish_newsletter().await;
+
+    // Act
+    let response = app.get_subject_data(&email).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["email"], email.as_str());
+    assert_eq!(data["subscriptions"][0]["status"], "confirmed");
+    assert!(data["subscriptions"][0]["unsubscribe_token"].is_string());
+    assert_eq!(data["confirmation_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["sent_deliveries"].as_array().unwrap().len(), 1);
+    assert_eq!(data["pending_deliveries"].as_array().unwrap().len(), 1);
+    assert!(data["failed_deliveries"].as_array().unwrap().is_empty());
+    assert!(data["suppressed_at"].is_null());
+}
+
+#[tokio::test]
+async fn erasing_a_subject_removes_every_row_about_them() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.publish_newsletter().await;
+    app.dispatch_all_pending_emails().await;
+    app.publish_newsletter().await;
+    assert!(rows_about(&app, &email).await > 0);
+
+    // Act
+    let response = app.post_erase_subject(&email).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_eq!(rows_about(&app, &email).await, 0);
+    let data: serde_json::Value = app.get_subject_data(&email).await.json().await.unwrap();
+    assert!(data["subscriptions"].as_array().unwrap().is_empty());
+    assert!(data["suppressed_at"].is_string());
+    // The pending delivery of the second issue was cancelled
+    let n_cancelled: i32 =
+        sqlx::query_scalar!("SELECT sum(n_cancelled)::int AS \"n!\" FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_cancelled, 1);
+}
+
+#[tokio::test]
+async fn erasing_ignores_the_case_of_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.post_erase_subject(&email.to_uppercase()).await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_eq!(rows_about(&app, &email).await, 0);
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("has been erased"));
+}
+
+#[tokio::test]
+async fn erasing_an_unknown_address_says_nothing_was_held() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.post_erase_subject("ursula@example.com").await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("Nothing was held about ursula@example.com."));
+}
+
+#[tokio::test]
+async fn the_erased_address_is_escaped_in_the_flash_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    app.post_erase_subject("<script>alert(1)</script>@example.com")
+        .await;
+    // Assert
+    let html_page = app.get_subscribers_html("").await;
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;alert(1)&lt;/script&gt;@example.com"));
+}
+
+#[tokio::test]
+async fn an_erased_address_is_not_imported_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_erase_subject("ursula@example.com").await;
+    // Act
+    let response = app
+        .post_subscribers_import(
+            "email,name\nUrsula@Example.com,Ursula\noctavia@example.com,Octavia",
+            "confirmed",
+        )
+        .await;
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("1 imported, 0 duplicates, 0 invalid."));
+    assert!(html_page.contains("1 skipped because they were erased at their request."));
+    let emails: Vec<String> = sqlx::query_scalar!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(emails, vec!["octavia@example.com".to_string()]);
+}
+
+#[tokio::test]
+async fn a_data_request_needs_an_email_address() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_subject_data("  ").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
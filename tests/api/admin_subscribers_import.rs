@@ -0,0 +1,181 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_statuses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .post_subscribers_import("email,name\nursula@example.com,Ursula", "confirmed")
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(subscriber_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn imported_rows_are_reported_one_by_one() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'octavia@example.com', 'Octavia', now(), 'confirmed')
+        "#,
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let csv = "Name,Email\n\
+        Ursula,ursula@example.com\n\
+        Octavia,octavia@example.com\n\
+        Nobody,not-an-email\n\
+        Ursula again,ursula@example.com";
+    // Act
+    let response = app.post_subscribers_import(csv, "confirmed").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("1 imported, 2 duplicates, 1 invalid."));
+    assert!(html_page.contains("not-an-email is not a valid subscriber email."));
+    assert_eq!(
+        subscriber_statuses(&app).await,
+        vec![
+            ("octavia@example.com".to_string(), "confirmed".to_string()),
+            ("ursula@example.com".to_string(), "confirmed".to_string()),
+        ]
+    );
+    let n_unsubscribe_tokens =
+        sqlx::query!(r#"SELECT count(*) AS "count!" FROM unsubscribe_tokens"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_unsubscribe_tokens, 1);
+}
+
+#[tokio::test]
+async fn double_opt_in_imports_send_a_confirmation_email_to_each_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name\nursula@example.com,Ursula\noctavia@example.com,Octavia";
+    // Act
+    let response = app.post_subscribers_import(csv, "double_opt_in").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("2 imported, 0 duplicates, 0 invalid."));
+    for (_, status) in subscriber_statuses(&app).await {
+        assert_eq!(status, "pending_confirmation");
+    }
+    // The delivery worker sends them
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn double_opt_in_imports_do_not_send_emails_themselves() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name\nursula@example.com,Ursula";
+    // Act
+    let response = app.post_subscribers_import(csv, "double_opt_in").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let n_queued = sqlx::query!(r#"SELECT count(*) AS "count!" FROM confirmation_email_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 1);
+}
+
+#[tokio::test]
+async fn duplicates_are_detected_regardless_of_case() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'octavia@example.com', 'Octavia', now(), 'confirmed')
+        "#,
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let csv = "email,name\n\
+        ursula@example.com,Ursula\n\
+        Ursula@Example.com,Ursula\n\
+        OCTAVIA@example.com,Octavia";
+    // Act
+    let response = app.post_subscribers_import(csv, "confirmed").await;
+    // Assert
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("1 imported, 2 duplicates, 0 invalid."));
+    assert_eq!(subscriber_statuses(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn a_file_without_the_expected_columns_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app
+        .post_subscribers_import("address\nursula@example.com", "confirmed")
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/subscribers/import", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The file must have an email and a name column."));
+}
@@ -0,0 +1,309 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Another browser, to keep a second user logged in next to `app.api_client`.
+fn new_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
+async fn log_in(
+    app: &TestApp,
+    client: &reqwest::Client,
+    username: &str,
+    password: &str,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": username,
+            "password": password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_users() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_users().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    for role in ["editor", "viewer"] {
+        // Arrange
+        let app = spawn_app().await;
+        let user = TestUser::with_role(role);
+        user.store(&app.db_pool).await;
+        user.login(&app).await;
+        // Act
+        let response = app.get_users().await;
+        let invite_response = app
+            .post_invite_user(&serde_json::json!({
+                "username": "ursula",
+                "email": "ursula@example.com",
+                "role": "owner"
+            }))
+            .await;
+        // Assert
+        assert_eq!(response.status().as_u16(), 403, "Role: {}", role);
+        assert_eq!(invite_response.status().as_u16(), 403, "Role: {}", role);
+        let html_page = app.get_admin_dashboard_html().await;
+        assert!(!html_page.contains("/admin/users"));
+    }
+}
+
+#[tokio::test]
+async fn viewers_can_browse_but_not_publish_nor_manage_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+    // Act
+    let list_response = app.get_subscribers("").await;
+    let publish_response = app.post_newsletters(&newsletter_request_body()).await;
+    let delete_response = app.post_subscriber_action(Uuid::new_v4(), "delete").await;
+    // Assert
+    assert_eq!(list_response.status().as_u16(), 200);
+    assert_eq!(publish_response.status().as_u16(), 403);
+    assert_eq!(delete_response.status().as_u16(), 403);
+    let n_issues = sqlx::query!("SELECT count(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn editors_can_publish_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+    // Act
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn an_invited_user_chooses_their_password_and_logs_in_with_their_role() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Invite
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "username": "ursula",
+            "email": "ursula@example.com",
+            "role": "editor"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("An invitation has been sent to ursula@example.com."));
+    assert!(html_page.contains("ursula (ursula@example.com), editor"));
+
+    // Act - Part 2 - Follow the link in the email
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let invitation_link = app.get_confirmation_links(email_request).html;
+    let client = new_client();
+    let response = client.get(invitation_link.clone()).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Welcome ursula!"));
+
+    // Act - Part 3 - Choose a password
+    let password = Uuid::new_v4().to_string();
+    let response = client
+        .post(invitation_link.clone())
+        .form(&serde_json::json!({
+            "new_password": &password,
+            "new_password_check": &password
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 4 - Log in
+    let response = log_in(&app, &client, "ursula", &password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = get_dashboard(&app, &client).await.text().await.unwrap();
+    assert!(html_page.contains("Role: editor"));
+
+    // Act - Part 5 - The invitation cannot be used twice
+    let response = client.get(invitation_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn an_invitation_is_not_accepted_with_mismatched_passwords() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_invite_user(&serde_json::json!({
+        "username": "ursula",
+        "email": "ursula@example.com",
+        "role": "viewer"
+    }))
+    .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let invitation_link = app.get_confirmation_links(email_request).html;
+    // Act
+    let response = new_client()
+        .post(invitation_link.clone())
+        .form(&serde_json::json!({
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string()
+        }))
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_is_redirect_to(&response, invitation_link.path());
+    let n_users =
+        sqlx::query!("SELECT count(*) AS \"count!\" FROM users WHERE username = 'ursula'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_users, 0);
+}
+
+#[tokio::test]
+async fn disabled_users_are_logged_out_until_they_are_enabled_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&app.db_pool).await;
+    let client = new_client();
+    log_in(&app, &client, &editor.username, &editor.password).await;
+    assert_eq!(get_dashboard(&app, &client).await.status().as_u16(), 200);
+
+    // Act - Part 1 - Disable
+    let response = app.post_user_action(editor.user_id, "disable").await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Assert - Part 1 - Their session is over, and they cannot log in again
+    assert_is_redirect_to(&get_dashboard(&app, &client).await, "/login");
+    let response = log_in(&app, &client, &editor.username, &editor.password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Enable
+    app.post_user_action(editor.user_id, "enable").await;
+
+    // Assert - Part 2
+    let response = log_in(&app, &client, &editor.username, &editor.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn owners_cannot_disable_or_delete_themselves() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act - Part 1 - Disable
+    app.post_user_action(app.test_user.user_id, "disable").await;
+    // Assert - Part 1
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("You cannot disable your own account."));
+    // Act - Part 2 - Delete
+    app.post_user_action(app.test_user.user_id, "delete").await;
+    // Assert - Part 2
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("You cannot delete your own account."));
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_deleted_user_is_gone() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    // Act
+    let response = app.post_user_action(viewer.user_id, "delete").await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("The user has been deleted."));
+    assert!(!html_page.contains(&viewer.username));
+}
+
+#[tokio::test]
+async fn invitation_tokens_are_not_stored_in_plaintext() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_invite_user(&serde_json::json!({
+        "username": "ursula",
+        "email": "ursula@example.com",
+        "role": "viewer"
+    }))
+    .await;
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let invitation_link = app.get_confirmation_links(email_request).html;
+    let token = invitation_link
+        .path_segments()
+        .unwrap()
+        .next_back()
+        .unwrap();
+    let stored: String = sqlx::query_scalar!("SELECT token_hash FROM user_invitations")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored, token);
+    assert!(!stored.contains(token));
+}
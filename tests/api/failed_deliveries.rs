@@ -0,0 +1,220 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, PostmarkBatchResponse, TestApp,
+};
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn publish_rejected_newsletter(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::rejecting_all())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_failed_deliveries().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_requeue_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.post_requeue_all_failed_deliveries().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_listed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_rejected_newsletter(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    // Act
+    let html_page = app.get_failed_deliveries_html().await;
+    // Assert
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains(&email));
+}
+
+#[tokio::test]
+async fn a_failed_delivery_can_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_rejected_newsletter(&app).await;
+    let failed =
+        sqlx::query!("SELECT newsletter_issue_id, subscriber_email FROM failed_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act - Part 1 - Re-enqueue the delivery
+    let response = app
+        .post_requeue_failed_delivery(&serde_json::json!({
+            "newsletter_issue_id": failed.newsletter_issue_id,
+            "subscriber_email": failed.subscriber_email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failed");
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>The delivery has been re-enqueued.</i></p>"));
+    assert!(html_page.contains("There are no failed deliveries."));
+    // Act - Part 3 - Deliver it
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn all_failed_deliveries_can_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::rejecting_all())
+        // Both deliveries go out in the same batch
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_newsletters(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+    drop(_mock_guard);
+    // Act
+    let response = app.post_requeue_all_failed_deliveries().await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failed");
+    // Assert
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>2 deliveries have been re-enqueued.</i></p>"));
+    let n_queued = sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 2);
+}
+
+#[tokio::test]
+async fn a_failed_delivery_that_is_already_queued_stays_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_rejected_newsletter(&app).await;
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) \
+        SELECT newsletter_issue_id, subscriber_email FROM failed_deliveries"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // Act
+    let response = app.post_requeue_all_failed_deliveries().await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failed");
+    // Assert
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>0 deliveries have been re-enqueued.</i></p>"));
+    let n_failed = sqlx::query!("SELECT count(*) as \"count!\" FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_failed, 1);
+}
+
+#[tokio::test]
+async fn a_batch_with_only_invalid_addresses_sends_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    sqlx::query!("UPDATE subscriptions SET email = 'not-an-email-address'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_newsletters(&newsletter_request_body).await;
+    // Act
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let n_failed = sqlx::query!("SELECT count(*) AS \"count!\" FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_failed, 1);
+}
+
+#[tokio::test]
+async fn failed_deliveries_to_unsubscribed_people_are_not_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_rejected_newsletter(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let response = app.post_requeue_all_failed_deliveries().await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failed");
+    // Assert
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>0 deliveries have been re-enqueued.</i></p>"));
+    let n_queued = sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
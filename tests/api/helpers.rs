@@ -5,12 +5,17 @@ use fake::faker::name::en::Name;
 use fake::Fake;
 use once_cell::sync::Lazy;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use wiremock::matchers::{method, path};
-use wiremock::{Mock, MockServer, ResponseTemplate};
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
-use zero2prod::email_client::EmailClient;
+use wiremock::{Mock, MockServer, Respond, ResponseTemplate};
+use zero2prod::configuration::EmailProvider;
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings, WorkerSettings};
+use zero2prod::email_client::EmailSender;
 use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::rate_limiter::RateLimiter;
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -26,7 +31,11 @@ pub struct TestApp {
     pub port: u16,
     pub api_client: reqwest::Client,
     pub test_user: TestUser,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailSender>,
+    pub worker_settings: WorkerSettings,
+    pub rate_limiter: RateLimiter,
+    pub configuration: Settings,
+    pub shutdown: CancellationToken,
 }
 
 impl TestApp {
@@ -63,6 +72,26 @@ impl TestApp {
         ConfirmationLinks { html, plain_text }
     }
 
+    /// The `List-Unsubscribe` link of the first email in a batch sent to Postmark.
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body[0]["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .unwrap();
+        let raw_link = header["Value"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        let mut unsubscribe_link = reqwest::Url::parse(raw_link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/subscriptions", &self.address))
@@ -73,6 +102,15 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_newsletters(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/newsletters", &self.address))
@@ -85,6 +123,62 @@ impl TestApp {
         self.get_newsletters().await.text().await.unwrap()
     }
 
+    pub async fn get_newsletter_issue(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_issue_html(&self, newsletter_issue_id: Uuid) -> String {
+        self.get_newsletter_issue(newsletter_issue_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reschedule_newsletter_issue<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/reschedule",
+                &self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `action` is one of "pause", "resume" or "cancel".
+    pub async fn post_newsletter_issue_action(
+        &self,
+        newsletter_issue_id: Uuid,
+        action: &str,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/{}",
+                &self.address, newsletter_issue_id, action
+            ))
+            .form(&serde_json::json!({ "idempotency_key": idempotency_key }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletters<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -97,6 +191,25 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Publish an issue right away through the admin form, returning its id.
+    pub async fn publish_newsletter(&self) -> Uuid {
+        let newsletter_request_body = serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        });
+        let response = self.post_newsletters(&newsletter_request_body).await;
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        sqlx::query!(
+            "SELECT newsletter_issue_id FROM newsletter_issues ORDER BY queued_at DESC NULLS LAST LIMIT 1"
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+    }
+
     pub async fn get_login_html(&self) -> String {
         self.api_client
             .get(&format!("{}/login", &self.address))
@@ -108,6 +221,36 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn post_password_reset(&self, username: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/password_reset", &self.address))
+            .form(&[("username", username)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_new_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password_reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_second_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/two_factor", &self.address))
+            .form(&[("code", code)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
             .get(&format!("{}/admin/dashboard", &self.address))
@@ -143,6 +286,41 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn get_two_factor_settings(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/two_factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_two_factor_settings_html(&self) -> String {
+        self.get_two_factor_settings().await.text().await.unwrap()
+    }
+
+    pub async fn post_enable_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two_factor", &self.address))
+            .form(&[("code", code)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `action` is either `disable` or `recovery_codes`.
+    pub async fn post_two_factor_action(
+        &self,
+        action: &str,
+        current_password: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two_factor/{}", &self.address, action))
+            .form(&[("current_password", current_password)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/admin/logout", &self.address))
@@ -150,12 +328,200 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn get_failed_deliveries(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliveries/failed", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.get_failed_deliveries().await.text().await.unwrap()
+    }
+
+    pub async fn post_requeue_failed_delivery<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/deliveries/failed/requeue", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_requeue_all_failed_deliveries(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/deliveries/failed/requeue_all",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_subscriber_details_html(&self, subscriber_id: Uuid) -> String {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `action` is one of `confirm`, `unsubscribe` or `delete`.
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_export(&self, format: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/export?format={}",
+                &self.address, format
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_issue_export(
+        &self,
+        newsletter_issue_id: Uuid,
+        format: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}/export?format={}",
+                &self.address, newsletter_issue_id, format
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subject_data(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/data", &self.address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_erase_subject(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/subscribers/erase", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `mode` is either `confirmed` or `double_opt_in`.
+    pub async fn post_subscribers_import(&self, csv: &str, mode: &str) -> reqwest::Response {
+        let boundary = Uuid::new_v4().to_string();
+        let body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+            {mode}\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n"
+        );
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.get_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_invite_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users/invite", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `action` is one of `disable`, `enable`, `delete` or `reset_two_factor`.
+    pub async fn post_user_action(&self, user_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                &self.address, user_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.rate_limiter,
+                &self.worker_settings,
+                &self.configuration.application.base_url,
+                &self.shutdown,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -163,18 +529,82 @@ impl TestApp {
     }
 }
 
+/// Answers Postmark's batch endpoint with one result per email in the batch,
+/// rejecting the first `n_rejected` emails and accepting the others.
+pub struct PostmarkBatchResponse {
+    n_rejected: usize,
+    delay: Duration,
+}
+
+impl PostmarkBatchResponse {
+    pub fn accepting_all() -> Self {
+        Self::rejecting_first(0)
+    }
+
+    pub fn rejecting_all() -> Self {
+        Self::rejecting_first(usize::MAX)
+    }
+
+    pub fn rejecting_first(n_rejected: usize) -> Self {
+        Self {
+            n_rejected,
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl Respond for PostmarkBatchResponse {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = emails
+            .iter()
+            .enumerate()
+            .map(|(i, email)| {
+                if i < self.n_rejected {
+                    serde_json::json!({
+                        "ErrorCode": 406,
+                        "Message": "You tried to send to a recipient that has been marked as inactive.",
+                        "To": email["To"],
+                    })
+                } else {
+                    serde_json::json!({
+                        "ErrorCode": 0,
+                        "Message": "OK",
+                        "MessageID": Uuid::new_v4(),
+                        "To": email["To"],
+                    })
+                }
+            })
+            .collect();
+        ResponseTemplate::new(200)
+            .set_body_json(results)
+            .set_delay(self.delay)
+    }
+}
+
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: &'static str,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::with_role("owner")
+    }
+
+    pub fn with_role(role: &'static str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role,
         }
     }
 
@@ -191,17 +621,35 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)",
             self.user_id,
             self.username,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
         .expect("Failed to store test user.");
     }
 
+    /// Replace the stored hash with one computed with other argon2 parameters.
+    pub async fn rehash_password(&self, params: Params, pool: &PgPool) {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(self.password.as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+        sqlx::query!(
+            "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+            password_hash,
+            self.user_id,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to rehash the test user's password.");
+    }
+
     pub async fn login(&self, app: &TestApp) {
         app.post_login(&serde_json::json!({
             "username": &self.username,
@@ -222,7 +670,21 @@ pub async fn spawn_app() -> TestApp {
         config.database.database_name = Uuid::new_v4().to_string();
         // Use a random OS port
         config.application.port = 0;
+        // Every email goes to the mock server, whatever the local setup is
+        config.email_client.provider = EmailProvider::Postmark;
         config.email_client.base_url = email_server.uri();
+        // Retry failed deliveries straight away
+        config.worker.min_backoff_milliseconds = 0;
+        config.worker.max_backoff_milliseconds = 0;
+        // Background workers should only ever be woken up by notifications
+        config.worker.poll_interval_seconds = 3600;
+        // Do not slow down tests sending lots of emails
+        config.worker.messages_per_second = 1000;
+        config.worker.burst_size = 1000;
+        // Each test case counts its own subscription requests
+        config.subscriptions.rate_limit.key_prefix = Uuid::new_v4().to_string();
+        // ...and its own failed logins
+        config.login_throttle.key_prefix = Uuid::new_v4().to_string();
         config
     };
     // Create and migrate the database
@@ -236,7 +698,8 @@ pub async fn spawn_app() -> TestApp {
 
     let address = format!("http://127.0.0.1:{}", server_port);
 
-    tokio::spawn(server.run_until_stopped());
+    let shutdown = CancellationToken::new();
+    tokio::spawn(server.run_until_stopped(shutdown.clone()));
 
     let api_client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -251,7 +714,11 @@ pub async fn spawn_app() -> TestApp {
         port: server_port,
         test_user: TestUser::generate(),
         api_client,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().client(),
+        worker_settings: configuration.worker.clone(),
+        rate_limiter: configuration.worker.rate_limiter(),
+        configuration,
+        shutdown,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -326,3 +793,33 @@ pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .unwrap();
     app.get_confirmation_links(email_request)
 }
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    // We can then reuse the same helper and just add
+    // an extra step to actually call the confirmation link!
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Insert a subscriber straight into the database, with any status.
+pub async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        id,
+        email,
+        name,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    id
+}
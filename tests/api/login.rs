@@ -1,4 +1,6 @@
 use crate::helpers::{assert_is_redirect_to, spawn_app};
+use argon2::Params;
+use std::time::Duration;
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -45,3 +47,100 @@ async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
     // Assert
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn too_many_failed_attempts_block_even_the_right_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let free_failures = app.configuration.login_throttle.free_failures;
+    for _ in 0..=free_failures {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": &app.test_user.username,
+                "password": "wrong-password"
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn unknown_usernames_are_throttled_like_existing_ones() {
+    // Arrange
+    let app = spawn_app().await;
+    let free_failures = app.configuration.login_throttle.free_failures;
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    for _ in 0..=free_failures {
+        app.post_login(&login_body).await;
+        app.get_login_html().await;
+    }
+    // Act
+    let response = app.post_login(&login_body).await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user
+        .rehash_password(Params::new(4096, 1, 1, None).unwrap(), &app.db_pool)
+        .await;
+    let settings = &app.configuration.password_hashing;
+    let current_params = format!(
+        "m={},t={},p={}",
+        settings.memory_kib, settings.iterations, settings.parallelism
+    );
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    // The upgrade happens in the background
+    let mut password_hash = String::new();
+    for _ in 0..50 {
+        password_hash = sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            app.test_user.user_id,
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash;
+        if password_hash.contains(&current_params) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(password_hash.contains(&current_params));
+    // The new hash still matches the password
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -1,8 +1,22 @@
 mod admin_dashboard;
+mod admin_export;
+mod admin_subscribers;
+mod admin_subscribers_gdpr;
+mod admin_subscribers_import;
+mod admin_users;
 mod change_password;
+mod failed_deliveries;
 mod health_check;
 mod helpers;
 mod login;
 mod newsletter;
+mod newsletter_actions;
+mod newsletter_progress;
+mod newsletter_scheduling;
+mod password_reset;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_purge;
+mod two_factor;
+mod unsubscribe;
@@ -1,8 +1,13 @@
 use std::time::Duration;
 
-use crate::helpers::{assert_is_redirect_to, create_unconfirmed_subscriber, spawn_app, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    PostmarkBatchResponse,
+};
+use tokio_util::sync::CancellationToken;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     // Arrange
@@ -61,9 +66,9 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.test_user.login(&app).await;
 
     create_confirmed_subscriber(&app).await;
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponse::accepting_all())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -87,17 +92,6 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.dispatch_all_pending_emails().await;
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    // We can then reuse the same helper and just add
-    // an extra step to actually call the confirmation link!
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
     // Arrange
@@ -166,9 +160,9 @@ async fn newsletter_creation_is_idempotent() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponse::accepting_all())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -200,17 +194,51 @@ async fn newsletter_creation_is_idempotent() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn deliveries_are_sent_in_batches() {
+    // Arrange
+    let mut app = spawn_app().await;
+    for _ in 0..5 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    app.worker_settings.batch_size = 2;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let n_sent = sqlx::query!("SELECT n_sent FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n_sent;
+    assert_eq!(n_sent, 5);
+}
+
 #[tokio::test]
 async fn concurrent_form_submission_is_handled_gracefully() {
     // Arrange
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         // Setting a long delay to ensure that the second request
         // arrives before the first one completes
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(PostmarkBatchResponse::accepting_all().with_delay(Duration::from_secs(2)))
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -231,3 +259,241 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     );
     app.dispatch_all_pending_emails().await;
 }
+
+#[tokio::test]
+async fn transient_delivery_failures_are_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let n_failed = sqlx::query!("SELECT count(*) as \"count!\" FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_failed, 0);
+}
+
+#[tokio::test]
+async fn deliveries_are_dead_lettered_once_retries_are_exhausted() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let n_attempts = u64::from(app.worker_settings.max_retries) + 1;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(n_attempts)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let n_queued = sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    let failed = sqlx::query!("SELECT n_attempts, last_error FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the dead-lettered delivery.");
+    assert_eq!(i64::from(failed.n_attempts), n_attempts as i64);
+    assert!(!failed.last_error.is_empty());
+}
+
+#[tokio::test]
+async fn deliveries_rejected_by_the_email_api_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::rejecting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let failed = sqlx::query!("SELECT n_attempts FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the dead-lettered delivery.");
+    assert_eq!(failed.n_attempts, 1);
+}
+
+#[tokio::test]
+async fn throttled_deliveries_are_postponed_for_as_long_as_the_email_api_asks() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let task = sqlx::query!(
+        r#"
+        SELECT
+            n_retries,
+            execute_after > now() + interval '50 seconds' as "postponed!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The throttled delivery is no longer queued.");
+    assert_eq!(task.n_retries, 0);
+    assert!(task.postponed);
+}
+
+#[tokio::test]
+async fn publishing_an_issue_wakes_up_an_idle_worker() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        CancellationToken::new(),
+    ));
+    // Give the worker enough time to find an empty queue and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Assert - the poll interval is an hour, only the notification can wake the worker up
+    let mut n_queued = 1;
+    for _ in 0..50 {
+        n_queued = sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+        if n_queued == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    worker.abort();
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn concurrent_workers_deliver_each_issue_exactly_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let n_subscribers = 5;
+    for _ in 0..n_subscribers {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        // Sending sequentially would take far longer than we wait for below
+        .respond_with(PostmarkBatchResponse::accepting_all().with_delay(Duration::from_secs(1)))
+        .expect(n_subscribers)
+        .mount(&app.email_server)
+        .await;
+    let mut configuration = app.configuration.clone();
+    configuration.worker.concurrency = n_subscribers as u32;
+    // One delivery per task, or a single task would grab all of them
+    configuration.worker.batch_size = 1;
+    let worker = tokio::spawn(run_worker_until_stopped(
+        configuration,
+        CancellationToken::new(),
+    ));
+    // Act
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Assert
+    let mut n_queued = n_subscribers as i64;
+    for _ in 0..30 {
+        n_queued = sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+        if n_queued == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    worker.abort();
+    assert_eq!(n_queued, 0);
+}
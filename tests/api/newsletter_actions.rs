@@ -0,0 +1,139 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, PostmarkBatchResponse, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn apply(app: &TestApp, newsletter_issue_id: Uuid, action: &str) -> reqwest::Response {
+    let response = app
+        .post_newsletter_issue_action(newsletter_issue_id, action, &Uuid::new_v4().to_string())
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+    response
+}
+
+async fn n_queued(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_pause_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .post_newsletter_issue_action(Uuid::new_v4(), "pause", &Uuid::new_v4().to_string())
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn paused_issues_are_not_delivered_until_they_are_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = app.publish_newsletter().await;
+
+    // Act - Part 1 - Pause
+    apply(&app, issue_id, "pause").await;
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The delivery of the issue has been paused.</i></p>"));
+    {
+        let _mock_guard = Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(PostmarkBatchResponse::accepting_all())
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.dispatch_all_pending_emails().await;
+    }
+    assert_eq!(n_queued(&app).await, 1);
+
+    // Act - Part 2 - Resume
+    apply(&app, issue_id, "resume").await;
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The delivery of the issue has been resumed.</i></p>"));
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(n_queued(&app).await, 0);
+}
+
+#[tokio::test]
+async fn cancelling_an_issue_drops_its_pending_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = app.publish_newsletter().await;
+    // Act
+    apply(&app, issue_id, "cancel").await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("<tr><th>Status</th><td>cancelled</td></tr>"));
+    assert!(html_page.contains("<tr><th>Cancelled</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>0</td></tr>"));
+    assert_eq!(n_queued(&app).await, 0);
+}
+
+#[tokio::test]
+async fn issue_actions_are_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = app.publish_newsletter().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    // Act - Part 1 - Pause
+    let response = app
+        .post_newsletter_issue_action(issue_id, "pause", &idempotency_key)
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    app.get_newsletter_issue_html(issue_id).await;
+    // Act - Part 2 - Pause again with the same key
+    let response = app
+        .post_newsletter_issue_action(issue_id, "pause", &idempotency_key)
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    // Assert - The retry is not reported as a failure
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The delivery of the issue has been paused.</i></p>"));
+    assert!(!html_page.contains("can be paused"));
+}
+
+#[tokio::test]
+async fn only_paused_issues_can_be_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = app.publish_newsletter().await;
+    // Act
+    apply(&app, issue_id, "resume").await;
+    // Assert
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<p><i>Only paused issues can be resumed.</i></p>"));
+    assert!(html_page.contains("<tr><th>Status</th><td>published</td></tr>"));
+}
@@ -0,0 +1,89 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, PostmarkBatchResponse,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_progress_of_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_newsletter_issue(Uuid::new_v4()).await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_unknown_issue_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_newsletter_issue(Uuid::new_v4()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn published_issues_link_to_their_progress() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = app.publish_newsletter().await;
+    // Act
+    let html_page = app.get_newsletters_html().await;
+    // Assert
+    assert!(html_page.contains(&format!("/admin/newsletters/{}", issue_id)));
+}
+
+#[tokio::test]
+async fn the_progress_of_an_issue_is_tracked_as_it_is_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::rejecting_first(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    let issue_id = app.publish_newsletter().await;
+
+    // Assert - Part 1 - Nothing has been sent yet
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains("<tr><th>Recipients enqueued</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>First send</th><td>-</td></tr>"));
+
+    // Act - Part 2 - Deliver
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2 - One delivery succeeded, the other one was rejected
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<tr><th>Sent</th><td>1</td></tr>"));
+    assert!(html_page.contains(">1</a></td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>0</td></tr>"));
+    let issue =
+        sqlx::query!("SELECT queued_at, first_sent_at, last_sent_at FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert!(issue.queued_at.is_some());
+    assert!(issue.first_sent_at.is_some());
+    assert_eq!(issue.first_sent_at, issue.last_sent_at);
+
+    // Act - Part 3 - Re-enqueue the failed delivery
+    app.post_requeue_all_failed_deliveries().await;
+
+    // Assert - Part 3 - It is pending again
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains(">0</a></td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>1</td></tr>"));
+}
@@ -0,0 +1,240 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, PostmarkBatchResponse, TestApp,
+};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+use zero2prod::issue_delivery_worker::try_execute_task;
+
+fn tomorrow() -> DateTime<Utc> {
+    Utc::now() + std::time::Duration::from_secs(24 * 60 * 60)
+}
+
+async fn schedule_newsletter(app: &TestApp, send_at: DateTime<Utc>) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    "send_at": send_at.to_rfc3339(),
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn issue_status(app: &TestApp, newsletter_issue_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+/// Pretend the scheduled time of the issue has come.
+async fn make_due(app: &TestApp, newsletter_issue_id: Uuid) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET published_at = now() WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_they_are_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let issue_id = schedule_newsletter(&app, tomorrow()).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled for"));
+    assert_eq!(issue_status(&app, issue_id).await, "scheduled");
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_they_are_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = schedule_newsletter(&app, tomorrow()).await;
+    // Act
+    make_due(&app, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(issue_status(&app, issue_id).await, "published");
+}
+
+#[tokio::test]
+async fn a_send_time_in_the_past_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    "send_at": "2020-01-01T10:00",
+    });
+    // Act
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The send time must be in the future.</i></p>"));
+    let n_issues = sqlx::query!("SELECT count(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_can_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app, tomorrow()).await;
+    let send_at = tomorrow() + std::time::Duration::from_secs(60 * 60);
+    // Act
+    let response = app
+        .post_reschedule_newsletter_issue(
+            issue_id,
+            &serde_json::json!({ "send_at": send_at.to_rfc3339() }),
+        )
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("The issue has been rescheduled for"));
+    let published_at = sqlx::query!("SELECT published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .published_at;
+    assert_eq!(published_at.timestamp_micros(), send_at.timestamp_micros());
+}
+
+#[tokio::test]
+async fn a_cancelled_issue_is_never_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = schedule_newsletter(&app, tomorrow()).await;
+    // Act
+    let response = app
+        .post_newsletter_issue_action(issue_id, "cancel", &Uuid::new_v4().to_string())
+        .await;
+    make_due(&app, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("The issue has been cancelled."));
+    assert_eq!(issue_status(&app, issue_id).await, "cancelled");
+}
+
+#[tokio::test]
+async fn issues_that_went_out_cannot_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app, tomorrow()).await;
+    make_due(&app, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+    // Act
+    let response = app
+        .post_reschedule_newsletter_issue(
+            issue_id,
+            &serde_json::json!({ "send_at": tomorrow().to_rfc3339() }),
+        )
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("Only scheduled issues can be rescheduled."));
+    assert_eq!(issue_status(&app, issue_id).await, "published");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_cancel_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .post_newsletter_issue_action(Uuid::new_v4(), "cancel", &Uuid::new_v4().to_string())
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn due_issues_are_published_while_another_issue_is_still_sending() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .mount(&app.email_server)
+        .await;
+    let scheduled_issue_id = schedule_newsletter(&app, tomorrow()).await;
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    make_due(&app, scheduled_issue_id).await;
+    // Act - a single pass of the worker, with the queue not empty
+    try_execute_task(
+        &app.db_pool,
+        app.email_client.as_ref(),
+        &app.rate_limiter,
+        &app.worker_settings,
+        &app.configuration.application.base_url,
+        &app.shutdown,
+    )
+    .await
+    .unwrap();
+    // Assert
+    assert_eq!(issue_status(&app, scheduled_issue_id).await, "published");
+}
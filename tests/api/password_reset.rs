@@ -0,0 +1,210 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn give_email_to_test_user(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = 'admin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// The reset email goes out in the background.
+async fn wait_for_reset_link(app: &TestApp) -> reqwest::Url {
+    for _ in 0..50 {
+        if let Some(email_request) = app.email_server.received_requests().await.unwrap().pop() {
+            return app.get_confirmation_links(&email_request).html;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("No password reset email was sent");
+}
+
+fn token_of(reset_link: &reqwest::Url) -> String {
+    reset_link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn a_forgotten_password_can_be_reset_from_the_emailed_link() {
+    // Arrange
+    let app = spawn_app().await;
+    give_email_to_test_user(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Ask for a link
+    let response = app.post_password_reset(&app.test_user.username).await;
+    assert_is_redirect_to(&response, "/login");
+    let reset_link = wait_for_reset_link(&app).await;
+
+    // Act - Part 2 - Follow it
+    let response = reqwest::get(reset_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 3 - Choose a new password
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_new_password(&serde_json::json!({
+            "token": token_of(&reset_link),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your password has been reset, you can log in."));
+
+    // Assert - The old password no longer works, the new one does
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert - The link cannot be used twice
+    let response = reqwest::get(reset_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn resetting_a_password_logs_the_user_out_everywhere() {
+    // Arrange
+    let app = spawn_app().await;
+    give_email_to_test_user(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+    app.post_password_reset(&app.test_user.username).await;
+    let reset_link = wait_for_reset_link(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    // Act
+    reqwest::Client::new()
+        .post(format!("{}/password_reset/confirm", &app.address))
+        .form(&serde_json::json!({
+            "token": token_of(&reset_link),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_answer_is_the_same_whether_or_not_the_username_exists() {
+    // Arrange
+    let app = spawn_app().await;
+    give_email_to_test_user(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let known = app.post_password_reset(&app.test_user.username).await;
+    let known_page = app.get_login_html().await;
+    let unknown = app.post_password_reset("nobody").await;
+    let unknown_page = app.get_login_html().await;
+    // Assert
+    assert_eq!(known.status(), unknown.status());
+    assert_eq!(known.headers()["Location"], unknown.headers()["Location"]);
+    assert_eq!(known_page, unknown_page);
+    assert!(known_page.contains("If this account exists"));
+}
+
+#[tokio::test]
+async fn an_expired_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    give_email_to_test_user(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_password_reset(&app.test_user.username).await;
+    let reset_link = wait_for_reset_link(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let new_password = Uuid::new_v4().to_string();
+    // Act
+    let response = app
+        .post_new_password(&serde_json::json!({
+            "token": token_of(&reset_link),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn new_passwords_must_match() {
+    // Arrange
+    let app = spawn_app().await;
+    give_email_to_test_user(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_password_reset(&app.test_user.username).await;
+    let reset_link = wait_for_reset_link(&app).await;
+    let token = token_of(&reset_link);
+    // Act
+    let response = app
+        .post_new_password(&serde_json::json!({
+            "token": &token,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/password_reset/confirm?token={}", token),
+    );
+    // The link still works
+    let response = reqwest::get(reset_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
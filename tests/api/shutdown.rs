@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, PostmarkBatchResponse,
+};
+use tokio_util::sync::CancellationToken;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+
+#[tokio::test]
+async fn the_worker_finishes_its_in_flight_delivery_before_stopping() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all().with_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        shutdown.clone(),
+    ));
+    let newsletter_request_body = serde_json::json!({
+    "title": "Newsletter title",
+    "text_content": "Newsletter body as plain text",
+    "html_content": "<p>Newsletter body as HTML</p>",
+    "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let n_requests = app.email_server.received_requests().await.unwrap().len();
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Wait for the delivery to be in flight
+    while app.email_server.received_requests().await.unwrap().len() == n_requests {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    // Act
+    shutdown.cancel();
+    let outcome = tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop after the shutdown signal.")
+        .unwrap();
+    // Assert
+    assert!(outcome.is_ok());
+    let n_queued = sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn an_idle_worker_stops_straight_away() {
+    // Arrange
+    let app = spawn_app().await;
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        shutdown.clone(),
+    ));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    // Act
+    shutdown.cancel();
+    // Assert - the poll interval is an hour
+    let outcome = tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop after the shutdown signal.")
+        .unwrap();
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn the_api_stops_accepting_connections_after_the_shutdown_signal() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    // Act
+    app.shutdown.cancel();
+    // Assert
+    let mut outcome = Ok(());
+    for _ in 0..50 {
+        outcome = reqwest::Client::new()
+            .get(format!("{}/health_check", &app.address))
+            .send()
+            .await
+            .map(|_| ());
+        if outcome.is_err() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(outcome.is_err());
+}
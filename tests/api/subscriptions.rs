@@ -152,3 +152,216 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_silently_drops_requests_filling_in_the_honeypot() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=spam.example.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_429_when_an_email_address_is_used_too_often() {
+    // Arrange
+    let app = spawn_app().await;
+    let max_requests = app
+        .configuration
+        .subscriptions
+        .rate_limit
+        .max_requests_per_email;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(max_requests)
+        .mount(&app.email_server)
+        .await;
+    for _ in 0..max_requests {
+        let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    // Act - the limit does not care about case
+    let body = "name=le%20guin&email=Ursula_Le_Guin%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0);
+    assert!(retry_after <= app.configuration.subscriptions.rate_limit.window_seconds);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_429_when_an_ip_address_sends_too_many_requests() {
+    // Arrange
+    let app = spawn_app().await;
+    let max_requests = app
+        .configuration
+        .subscriptions
+        .rate_limit
+        .max_requests_per_ip;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(max_requests)
+        .mount(&app.email_server)
+        .await;
+    for i in 0..max_requests {
+        let body = format!("name=le%20guin&email=ursula_{}%40gmail.com", i);
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    // Act
+    let body = "name=le%20guin&email=someone_else%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn a_forged_forwarded_for_header_does_not_reset_the_ip_limit() {
+    // Arrange
+    let app = spawn_app().await;
+    let max_requests = app
+        .configuration
+        .subscriptions
+        .rate_limit
+        .max_requests_per_ip;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(max_requests)
+        .mount(&app.email_server)
+        .await;
+    let mut statuses = Vec::new();
+    // Act - a new made up address every time
+    for i in 0..=max_requests {
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", format!("198.51.100.{}", i))
+            .body(format!("name=le%20guin&email=ursula_{}%40gmail.com", i))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        statuses.push(response.status().as_u16());
+    }
+    // Assert
+    assert_eq!(statuses.last(), Some(&429));
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn subscribe_reports_the_invalid_field_as_a_problem() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "le guin", "email": "definitely-not-an-email"}),
+            "email",
+        ),
+        (
+            serde_json::json!({"email": "ursula_le_guin@gmail.com"}),
+            "name",
+        ),
+    ];
+    for (body, invalid_field) in test_cases {
+        // Act
+        let response = app.post_subscriptions_json(&body).await;
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 400);
+        assert_eq!(problem["invalid-params"][0]["name"], invalid_field);
+        assert!(problem["invalid-params"][0]["reason"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn subscribe_rejects_bodies_that_are_neither_forms_nor_json() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "text/plain")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 415);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 415);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_problem_for_malformed_json() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/json")
+        .body(r#"{"name": "le guin", "#)
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["title"], "Bad Request");
+    assert!(problem["detail"].is_string());
+}
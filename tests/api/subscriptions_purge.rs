@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+use zero2prod::purge_worker::purge_unconfirmed_subscribers;
+
+const RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[tokio::test]
+async fn subscribers_who_never_confirmed_are_purged_after_the_retention_period() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let n_purged = purge_unconfirmed_subscribers(&app.db_pool, RETENTION)
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(n_purged, 1);
+    let n_subscribers = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn recent_and_confirmed_subscribers_are_not_purged() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "UPDATE subscription_tokens SET created_at = now() - interval '8 days'
+        WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE status = 'confirmed')"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // Act
+    let n_purged = purge_unconfirmed_subscribers(&app.db_pool, RETENTION)
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(n_purged, 0);
+}
@@ -0,0 +1,245 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::TOTP;
+
+/// Turn two-factor authentication on for the logged-in test user, like they
+/// would with their authenticator app. Returns the authenticator and the
+/// recovery codes.
+async fn enrol(app: &TestApp) -> (TOTP, Vec<String>) {
+    let html_page = app.get_two_factor_settings_html().await;
+    let secret = html_page
+        .split("<code>")
+        .nth(1)
+        .and_then(|s| s.split("</code>").next())
+        .unwrap();
+    let totp = zero2prod::authentication::totp(secret, &app.test_user.username).unwrap();
+    let response = app
+        .post_enable_two_factor(&totp.generate_current().unwrap())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let recovery_codes = recovery_codes_in(&response.text().await.unwrap());
+    (totp, recovery_codes)
+}
+
+fn recovery_codes_in(html_page: &str) -> Vec<String> {
+    html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s.split("</code>").next().unwrap().to_string())
+        .collect()
+}
+
+/// The code used to enrol cannot be used again: this is the next one.
+fn next_code(totp: &TOTP) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    totp.generate(now.as_secs() + 30)
+}
+
+async fn log_in_with_password(app: &TestApp) -> reqwest::Response {
+    app.post_logout().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_set_up_two_factor_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_two_factor_settings().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_second_factor_form_needs_a_verified_password() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.post_login_second_factor("123456").await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn enrolled_users_need_a_code_after_their_password() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (totp, recovery_codes) = enrol(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    // Act - Part 1 - The password alone is not enough
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+
+    // Act - Part 2 - Give the code
+    let code = next_code(&totp);
+    let response = app.post_login_second_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+
+    // Act - Part 3 - The same code cannot be used twice
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let response = app.post_login_second_factor(&code).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_recovery_code_works_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enrol(&app).await;
+
+    // Act - Part 1 - Use it, typed in upper case
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_second_factor(&recovery_codes[0].to_uppercase())
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("9 unused recovery codes left."));
+
+    // Act - Part 2 - Use it again
+    log_in_with_password(&app).await;
+    let response = app.post_login_second_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_require_the_password_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (totp, _) = enrol(&app).await;
+    log_in_with_password(&app).await;
+
+    // Act - Part 1 - Wrong codes
+    for _ in 0..4 {
+        let response = app.post_login_second_factor("000000").await;
+        assert_is_redirect_to(&response, "/login/two_factor");
+    }
+    let response = app.post_login_second_factor("000000").await;
+
+    // Assert - The right code no longer helps
+    assert_is_redirect_to(&response, "/login");
+    let response = app.post_login_second_factor(&next_code(&totp)).await;
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn wrong_codes_count_as_failed_logins() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (totp, _) = enrol(&app).await;
+    log_in_with_password(&app).await;
+    let free_failures = app.configuration.login_throttle.free_failures;
+    for _ in 0..=free_failures {
+        let response = app.post_login_second_factor("000000").await;
+        assert_is_redirect_to(&response, "/login/two_factor");
+    }
+    // Act
+    let response = app.post_login_second_factor(&next_code(&totp)).await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_authentication_is_not_turned_on_with_a_wrong_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("Two-factor authentication is off."));
+    assert!(html_page.contains("otpauth://totp/"));
+    // Act
+    let response = app.post_enable_two_factor("abcdef").await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("The code is incorrect."));
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn turning_two_factor_authentication_off_needs_the_password() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    enrol(&app).await;
+
+    // Act - Part 1 - Wrong password
+    let response = app
+        .post_two_factor_action("disable", "wrong-password")
+        .await;
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("The current password is incorrect."));
+    assert!(html_page.contains("Two-factor authentication is on."));
+
+    // Act - Part 2 - Right password
+    app.post_two_factor_action("disable", &app.test_user.password)
+        .await;
+    let html_page = app.get_two_factor_settings_html().await;
+    assert!(html_page.contains("Two-factor authentication is off."));
+
+    // Assert
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn new_recovery_codes_replace_the_old_ones() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (_, old_recovery_codes) = enrol(&app).await;
+    // Act
+    let response = app
+        .post_two_factor_action("recovery_codes", &app.test_user.password)
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let new_recovery_codes = recovery_codes_in(&response.text().await.unwrap());
+    assert_eq!(new_recovery_codes.len(), 10);
+    log_in_with_password(&app).await;
+    let response = app.post_login_second_factor(&old_recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let response = app.post_login_second_factor(&new_recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn owners_can_turn_off_two_factor_authentication_for_other_users() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    enrol(&app).await;
+    let owner = TestUser::generate();
+    owner.store(&app.db_pool).await;
+    app.post_logout().await;
+    owner.login(&app).await;
+    // Act
+    let response = app
+        .post_user_action(app.test_user.user_id, "reset_two_factor")
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("Two-factor authentication has been turned off for the user."));
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
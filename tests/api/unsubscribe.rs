@@ -0,0 +1,188 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, PostmarkBatchResponse, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+/// Deliver an issue to the only subscriber and return the unsubscribe link it carried.
+async fn receive_unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.publish_newsletter().await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_unsubscribe_link(&email_request)
+}
+
+async fn subscription_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn newsletter_emails_support_one_click_unsubscribe() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    // Act
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+    // Assert
+    assert_eq!(unsubscribe_link.path(), "/subscriptions/unsubscribe");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body[0]["Headers"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({
+            "Name": "List-Unsubscribe-Post",
+            "Value": "List-Unsubscribe=One-Click"
+        })));
+}
+
+#[tokio::test]
+async fn a_one_click_unsubscribe_request_unsubscribes_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+    // Act - This is what mail clients send, as per RFC 8058
+    let response = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn opening_the_unsubscribe_link_asks_for_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+    // Act
+    let response = reqwest::get(unsubscribe_link.clone()).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<form action="{}?{}" method="post">"#,
+        unsubscribe_link.path(),
+        unsubscribe_link.query().unwrap()
+    )));
+    // Mail scanners following the link must not unsubscribe anybody
+    assert_eq!(subscription_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribed_people_do_not_receive_newsletter_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponse::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.publish_newsletter().await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn unsubscribing_cancels_the_deliveries_still_pending() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_link = receive_unsubscribe_link(&app).await;
+    app.publish_newsletter().await;
+    // Act
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Assert
+    let n_pending = sqlx::query!("SELECT count(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_pending, 0);
+    let n_cancelled = sqlx::query!("SELECT sum(n_cancelled) AS \"sum!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .sum;
+    assert_eq!(n_cancelled, 1);
+}
+
+#[tokio::test]
+async fn an_unknown_unsubscribe_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token=aaaaaaaaaaaaaaaaaaaaaaaaa",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_malformed_unsubscribe_token_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}